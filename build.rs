@@ -0,0 +1,22 @@
+use std::fs;
+
+/// Export the version of the latest migration, so that the server can refuse
+/// a database migrated by a newer greenwood.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+
+    let latest = fs::read_dir("migrations")
+        .expect("Can't read migrations directory")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|dir| {
+            dir.split('_')
+                .next()
+                .map(|version| version.replace('-', ""))
+        })
+        .max()
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=LATEST_MIGRATION={}", latest);
+}
@@ -0,0 +1,2 @@
+ALTER TABLE packages DROP COLUMN corrected;
+ALTER TABLE packages DROP COLUMN hidden;
@@ -0,0 +1,3 @@
+-- Releases hidden or patched by the overrides file
+ALTER TABLE packages ADD hidden BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE packages ADD corrected BOOLEAN NOT NULL DEFAULT 0;
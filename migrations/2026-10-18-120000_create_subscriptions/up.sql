@@ -0,0 +1,9 @@
+-- WebSub subscriptions to feeds
+CREATE TABLE subscriptions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    callback TEXT NOT NULL,
+    topic TEXT NOT NULL,
+    secret TEXT,
+    expires INTEGER NOT NULL,
+    UNIQUE (callback, topic)
+);
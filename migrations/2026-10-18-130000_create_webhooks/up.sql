@@ -0,0 +1,8 @@
+-- Outgoing webhooks for new releases matching a feed filter
+CREATE TABLE webhooks (
+    id INTEGER PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    filter TEXT NOT NULL,
+    release TEXT NOT NULL,
+    secret TEXT NOT NULL
+);
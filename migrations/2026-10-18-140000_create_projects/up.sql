@@ -0,0 +1,6 @@
+-- Uploaded elm.json files, identified by a hash of their content
+CREATE TABLE projects (
+    id TEXT PRIMARY KEY NOT NULL,
+    elm_json TEXT NOT NULL,
+    created INTEGER NOT NULL
+);
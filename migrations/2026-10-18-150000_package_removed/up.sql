@@ -0,0 +1,2 @@
+-- Releases missing from the upstream list of all packages
+ALTER TABLE packages ADD removed BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE packages DROP COLUMN original;
+ALTER TABLE packages DROP COLUMN added;
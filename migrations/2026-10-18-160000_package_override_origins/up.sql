@@ -0,0 +1,9 @@
+-- Releases added by the overrides file, and upstream values of the fields
+-- patched by it, so that overrides removed from the file can be reverted
+ALTER TABLE packages ADD added BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE packages ADD original TEXT;
+
+-- Releases inserted by the fix migrations, declared in the overrides file
+UPDATE packages SET added = 1
+WHERE (author = 'alex-tan' AND name = 'loadable' AND major = 1 AND minor = 0 AND patch = 0)
+   OR (author = 'showell' AND name = 'elm-data-util' AND major = 1 AND minor = 0 AND patch = 0);
@@ -0,0 +1,42 @@
+use crate::db::models::Package;
+use crate::rss;
+use chrono::{TimeZone, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// JSON representation of a package release, shared by webhooks, events
+/// and API endpoints.
+#[derive(Serialize)]
+pub struct PackageRelease<'a> {
+    pub author: &'a str,
+    pub name: &'a str,
+    pub version: String,
+    pub published: String,
+    pub summary: &'a str,
+    pub license: &'a str,
+    pub elm_version: &'a str,
+    pub dependencies: BTreeMap<String, String>,
+    pub url: String,
+    /// Added or patched by the overrides file
+    pub corrected: bool,
+}
+
+impl<'a> From<&'a Package> for PackageRelease<'a> {
+    fn from(package: &'a Package) -> PackageRelease<'a> {
+        PackageRelease {
+            author: &package.author,
+            name: &package.name,
+            version: format!("{}.{}.{}", package.major, package.minor, package.patch),
+            published: Utc
+                .timestamp_opt(package.timestamp, 0)
+                .unwrap()
+                .to_rfc3339(),
+            summary: &package.summary,
+            license: &package.license,
+            elm_version: &package.elm_version,
+            dependencies: serde_json::from_str(&package.dependencies).unwrap_or_default(),
+            url: rss::item_link(package),
+            corrected: package.corrected,
+        }
+    }
+}
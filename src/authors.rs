@@ -0,0 +1,76 @@
+use crate::api::PackageRelease;
+use crate::db;
+use crate::db::models::Package;
+use chrono::{Datelike, TimeZone, Utc};
+use diesel::sqlite::SqliteConnection;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Packages and activity of an author
+#[derive(Serialize)]
+pub struct Profile<'a> {
+    pub author: String,
+    pub packages: Vec<AuthorPackage<'a>>,
+    pub releases: usize,
+    pub first_published: String,
+    pub last_published: String,
+    /// Number of releases per year
+    pub activity: BTreeMap<i32, usize>,
+}
+
+#[derive(Serialize)]
+pub struct AuthorPackage<'a> {
+    pub package: String,
+    pub latest: PackageRelease<'a>,
+    pub first_published: String,
+    pub releases: usize,
+}
+
+/// Releases of each package of an author, from the oldest
+pub fn releases(conn: &SqliteConnection, author: &str) -> Vec<Vec<Package>> {
+    db::author_packages(conn, &author.to_string())
+        .into_iter()
+        .map(|name| db::package_releases(conn, &format!("{}/{}", author, name)))
+        .filter(|releases| !releases.is_empty())
+        .collect()
+}
+
+/// Profile of an author, None without any release
+pub fn profile<'a>(author: &str, releases: &'a [Vec<Package>]) -> Option<Profile<'a>> {
+    let all = || releases.iter().flatten();
+    let first = all().map(|pkg| pkg.timestamp).min()?;
+    let last = all().map(|pkg| pkg.timestamp).max()?;
+    let mut activity = BTreeMap::new();
+    for package in all() {
+        *activity.entry(date(package.timestamp).year()).or_insert(0) += 1;
+    }
+
+    let mut packages: Vec<AuthorPackage> = releases
+        .iter()
+        .filter_map(|releases| {
+            let latest = releases
+                .iter()
+                .max_by_key(|pkg| (pkg.major, pkg.minor, pkg.patch))?;
+            Some(AuthorPackage {
+                package: format!("{}/{}", latest.author, latest.name),
+                latest: latest.into(),
+                first_published: date(releases.first()?.timestamp).to_rfc3339(),
+                releases: releases.len(),
+            })
+        })
+        .collect();
+    packages.sort_by(|a, b| a.package.cmp(&b.package));
+
+    Some(Profile {
+        author: author.to_string(),
+        packages,
+        releases: all().count(),
+        first_published: date(first).to_rfc3339(),
+        last_published: date(last).to_rfc3339(),
+        activity,
+    })
+}
+
+fn date(timestamp: i64) -> chrono::DateTime<Utc> {
+    Utc.timestamp_opt(timestamp, 0).unwrap()
+}
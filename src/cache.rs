@@ -0,0 +1,86 @@
+use crate::metrics;
+use crate::query;
+use crate::rss;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Rendered responses are dropped when there are too many different queries,
+/// to bound memory usage.
+const MAX_ENTRIES: usize = 1024;
+
+/// In-memory cache of rendered responses, invalidated when packages
+/// are inserted by the synchronization thread.
+#[derive(Clone, Default)]
+pub struct Cache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    generation: u64,
+    entries: HashMap<Key, String>,
+}
+
+#[derive(Hash, PartialEq, Eq)]
+pub struct Key {
+    route: String,
+    query: String,
+    agent: Agent,
+}
+
+/// Feeds are rendered differently for some user agents
+#[derive(Hash, PartialEq, Eq)]
+enum Agent {
+    Slack,
+    Other,
+}
+
+impl Key {
+    pub fn new(route: &str, query: &HashMap<String, String>, user_agent: &str) -> Key {
+        Key {
+            route: route.to_string(),
+            query: query::encode(query),
+            agent: if rss::is_slack(user_agent) {
+                Agent::Slack
+            } else {
+                Agent::Other
+            },
+        }
+    }
+}
+
+impl Cache {
+    /// Get a cached response or render it. A response rendered while the
+    /// cache is invalidated is not cached as it may be outdated.
+    pub fn get_or_render<F>(&self, key: Key, render: F) -> String
+    where
+        F: FnOnce() -> String,
+    {
+        let generation = {
+            let inner = self.inner.lock().unwrap();
+            if let Some(response) = inner.entries.get(&key) {
+                metrics::cache(true);
+                return response.clone();
+            }
+            metrics::cache(false);
+            inner.generation
+        };
+
+        let response = render();
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation == generation {
+            if inner.entries.len() >= MAX_ENTRIES {
+                inner.entries.clear();
+            }
+            inner.entries.insert(key, response.clone());
+        }
+        response
+    }
+
+    pub fn invalidate(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.generation += 1;
+        inner.entries.clear();
+    }
+}
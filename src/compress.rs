@@ -0,0 +1,231 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use warp::http::header::{CONTENT_ENCODING, CONTENT_TYPE, VARY};
+use warp::http::Response;
+
+/// Brotli quality for dynamic responses, the maximum being too slow
+/// to compress on each request.
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Identity,
+}
+
+impl Encoding {
+    fn name(self) -> Option<&'static str> {
+        match self {
+            Encoding::Brotli => Some("br"),
+            Encoding::Gzip => Some("gzip"),
+            Encoding::Identity => None,
+        }
+    }
+
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            Encoding::Brotli => Some("br"),
+            Encoding::Gzip => Some("gz"),
+            Encoding::Identity => None,
+        }
+    }
+}
+
+/// Encodings accepted by the client from its Accept-Encoding header,
+/// in order of preference and ignoring those less preferred than no
+/// encoding (`identity`). `*` stands for unlisted encodings.
+pub fn accepted(accept_encoding: &Option<String>) -> Vec<Encoding> {
+    let codings: Vec<(Option<Encoding>, f32)> = accept_encoding
+        .as_ref()
+        .map(|header| header.split(',').filter_map(parse_coding).collect())
+        .unwrap_or_default();
+
+    let mut encodings: Vec<(Encoding, f32)> = codings
+        .iter()
+        .filter_map(|(encoding, q)| encoding.map(|encoding| (encoding, *q)))
+        .collect();
+    let wildcard = codings.iter().find(|(encoding, _)| encoding.is_none());
+    if let Some((_, q)) = wildcard {
+        for encoding in [Encoding::Brotli, Encoding::Gzip] {
+            if !encodings.iter().any(|(listed, _)| *listed == encoding) {
+                encodings.push((encoding, *q));
+            }
+        }
+    }
+
+    // Prefer brotli, then gzip, then identity on equal quality values, the
+    // sort being stable
+    encodings.sort_by_key(|(encoding, _)| match encoding {
+        Encoding::Brotli => 0,
+        Encoding::Gzip => 1,
+        Encoding::Identity => 2,
+    });
+    encodings.sort_by(|(_, q1), (_, q2)| q2.total_cmp(q1));
+    encodings
+        .into_iter()
+        .filter(|(_, q)| *q > 0.0)
+        .map(|(encoding, _)| encoding)
+        .take_while(|encoding| *encoding != Encoding::Identity)
+        .collect()
+}
+
+/// Parse a coding and its quality value, ignoring unknown codings and
+/// malformed quality values. The encoding is none for `*`.
+fn parse_coding(coding: &str) -> Option<(Option<Encoding>, f32)> {
+    let mut fields = coding.split(';').map(str::trim);
+    let encoding = match fields.next()?.to_ascii_lowercase().as_str() {
+        "br" => Some(Encoding::Brotli),
+        "gzip" | "x-gzip" => Some(Encoding::Gzip),
+        "identity" => Some(Encoding::Identity),
+        "*" => None,
+        _ => return None,
+    };
+    let quality = match fields.find_map(|param| param.strip_prefix("q=")) {
+        Some(q) => q.parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?,
+        None => 1.0,
+    };
+    Some((encoding, quality))
+}
+
+pub fn compress(encoding: Encoding, body: &[u8]) -> Vec<u8> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut out = Vec::new();
+            {
+                let mut writer =
+                    brotli::CompressorWriter::new(&mut out, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                writer.write_all(body).expect("Can't compress with brotli");
+            }
+            out
+        }
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).expect("Can't compress with gzip");
+            encoder.finish().expect("Can't compress with gzip")
+        }
+        Encoding::Identity => body.to_vec(),
+    }
+}
+
+/// Build a response compressed with the preferred encoding of the client
+pub fn reply(
+    accept_encoding: &Option<String>,
+    content_type: &str,
+    body: &str,
+) -> Response<Vec<u8>> {
+    let encoding = accepted(accept_encoding)
+        .into_iter()
+        .next()
+        .unwrap_or(Encoding::Identity);
+
+    let mut response = Response::builder();
+    response
+        .header(CONTENT_TYPE, content_type)
+        .header(VARY, "accept-encoding");
+    if let Some(name) = encoding.name() {
+        response.header(CONTENT_ENCODING, name);
+    }
+    response
+        .body(compress(encoding, body.as_bytes()))
+        .expect("Can't build response")
+}
+
+/// Serve a static file compressed with an encoding accepted by the client,
+/// from its precompressed version when present, e.g. "elm.js.br" or
+/// "elm.js.gz" for "elm.js", or compressed on the fly for text files.
+pub fn static_file(
+    root: &str,
+    path: &str,
+    accept_encoding: &Option<String>,
+) -> Option<Response<Vec<u8>>> {
+    if path.split('/').any(|segment| segment == "..") {
+        return None;
+    }
+    let file = Path::new(root).join(path);
+    if !file.is_file() {
+        return None;
+    }
+    let encodings = accepted(accept_encoding);
+    let content_type = mime_guess::from_path(&file).first_or_octet_stream();
+
+    let precompressed = encodings.iter().find_map(|encoding| {
+        let mut compressed = file.clone().into_os_string();
+        compressed.push(".");
+        compressed.push(encoding.extension()?);
+        Some((*encoding, fs::read(&compressed).ok()?))
+    });
+    let (encoding, body) = match precompressed {
+        Some(found) => found,
+        None if compressible(&content_type) => {
+            let encoding = *encodings.first()?;
+            (encoding, compress(encoding, &fs::read(&file).ok()?))
+        }
+        None => return None,
+    };
+
+    Response::builder()
+        .header(CONTENT_TYPE, content_type.as_ref())
+        .header(CONTENT_ENCODING, encoding.name()?)
+        .header(VARY, "accept-encoding")
+        .body(body)
+        .ok()
+}
+
+fn compressible(content_type: &mime_guess::Mime) -> bool {
+    content_type.type_() == "text"
+        || ["javascript", "json", "xml", "svg"].iter().any(|name| {
+            content_type.subtype() == *name
+                || content_type.suffix().is_some_and(|suffix| suffix == *name)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Encoding::*;
+
+    fn accepted_by(header: &str) -> Vec<Encoding> {
+        accepted(&Some(header.to_string()))
+    }
+
+    #[test]
+    fn orders_encodings_by_quality() {
+        assert!(accepted(&None).is_empty());
+        assert_eq!(accepted_by("gzip, br"), vec![Brotli, Gzip]);
+        assert_eq!(accepted_by("br;q=0.5, gzip"), vec![Gzip, Brotli]);
+        assert_eq!(accepted_by("deflate, gzip;q=0.8"), vec![Gzip]);
+    }
+
+    #[test]
+    fn skips_refused_encodings() {
+        assert_eq!(accepted_by("br;q=0, gzip"), vec![Gzip]);
+        assert!(accepted_by("gzip;q=0.0").is_empty());
+    }
+
+    #[test]
+    fn expands_wildcard() {
+        assert_eq!(accepted_by("*"), vec![Brotli, Gzip]);
+        assert_eq!(accepted_by("br;q=0, *;q=0.5"), vec![Gzip]);
+        assert_eq!(accepted_by("gzip, *;q=0.1"), vec![Gzip, Brotli]);
+    }
+
+    #[test]
+    fn stops_at_identity() {
+        assert!(accepted_by("identity, gzip;q=0.5").is_empty());
+        assert_eq!(accepted_by("gzip, identity;q=0.5, br;q=0.1"), vec![Gzip]);
+    }
+
+    #[test]
+    fn ignores_malformed_quality_values() {
+        assert_eq!(accepted_by("gzip;q=nan"), vec![]);
+        assert_eq!(accepted_by("br;q=NaN, gzip;q=0.5"), vec![Gzip]);
+        assert_eq!(accepted_by("br;q=2, gzip;q=-1"), vec![]);
+        assert_eq!(accepted_by("br;q=inf, gzip;q=x"), vec![]);
+        assert_eq!(accepted_by("br;q=, gzip;;"), vec![Gzip]);
+    }
+}
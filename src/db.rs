@@ -1,25 +1,83 @@
+use crate::query;
 use crate::release::Release;
+use diesel::connection::SimpleConnection;
 use diesel::dsl::*;
 use diesel::expression::SqlLiteral;
 use diesel::prelude::*;
-use diesel::sql_types::Text;
-use diesel::sqlite::SqliteConnection;
-use dotenv::dotenv;
-use models::{NewPackage, Package};
+use diesel::r2d2::{ConnectionManager, CustomizeConnection};
+use diesel::sql_types::{BigInt, Text};
+use diesel::sqlite::{Sqlite, SqliteConnection};
+use diesel_migrations::MigrationConnection;
+use models::{NewPackage, Package, PackageChanges};
 use schema::packages;
 use schema::packages::dsl::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::env;
 
+pub mod jsonl;
 pub mod models;
+pub mod projects;
 pub mod schema;
+pub mod subscriptions;
+pub mod webhooks;
 
-pub fn connect() -> SqliteConnection {
-    dotenv().ok();
+pub type Pool = diesel::r2d2::Pool<ConnectionManager<SqliteConnection>>;
 
+/// Milliseconds to wait for a lock held by another connection, typically
+/// the synchronization thread inserting packages.
+const BUSY_TIMEOUT: u32 = 5000;
+
+#[derive(Debug)]
+struct Pragmas;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for Pragmas {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute(&format!(
+            "PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL;",
+            BUSY_TIMEOUT
+        ))
+        .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// Create the connection pool shared by request handlers and the
+/// synchronization thread.
+pub fn pool() -> Pool {
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
-    SqliteConnection::establish(&db_url).expect(&format!("Error connecting to {}", db_url))
+    diesel::r2d2::Pool::builder()
+        .connection_customizer(Box::new(Pragmas))
+        .build(ConnectionManager::new(db_url.as_str()))
+        .unwrap_or_else(|err| panic!("Error connecting to {}: {}", db_url, err))
+}
+
+embed_migrations!();
+
+/// Run pending migrations, refusing a database migrated by a newer greenwood.
+pub fn migrate(conn: &SqliteConnection) -> Result<(), String> {
+    let latest = env!("LATEST_MIGRATION");
+
+    diesel_migrations::setup_database(conn).map_err(|err| err.to_string())?;
+    let applied = conn
+        .latest_run_migration_version()
+        .map_err(|err| err.to_string())?;
+
+    match applied {
+        Some(version) if version.as_str() > latest => Err(format!(
+            "database schema version {} is newer than {} supported by this binary",
+            version, latest
+        )),
+        _ => embedded_migrations::run(conn).map_err(|err| err.to_string()),
+    }
+}
+
+/// Migrated in-memory database
+#[cfg(test)]
+pub fn memory() -> SqliteConnection {
+    use diesel::Connection;
+    let conn = SqliteConnection::establish(":memory:").expect("Can't open in-memory database");
+    migrate(&conn).expect("Can't migrate in-memory database");
+    conn
 }
 
 pub fn count_packages(conn: &SqliteConnection, pkg_format: i32) -> i64 {
@@ -30,6 +88,29 @@ pub fn count_packages(conn: &SqliteConnection, pkg_format: i32) -> i64 {
         .expect("Can't count packages from database")
 }
 
+/// Check that the database is reachable
+pub fn ping(conn: &SqliteConnection) -> bool {
+    diesel::sql_query("SELECT 1").execute(conn).is_ok()
+}
+
+/// Number of visible releases per package format
+pub fn count_releases_by_format(conn: &SqliteConnection) -> Vec<(i32, i64)> {
+    packages
+        .select((format, sql::<BigInt>("COUNT(*)")))
+        .filter(hidden.eq(false))
+        .group_by(format)
+        .load(conn)
+        .expect("Can't count releases from database")
+}
+
+pub fn newest_timestamp(conn: &SqliteConnection) -> Option<i64> {
+    packages
+        .select(max(timestamp))
+        .filter(hidden.eq(false))
+        .first(conn)
+        .expect("Can't get newest release from database")
+}
+
 pub fn has_package_version(conn: &SqliteConnection, pkg: &NewPackage) -> bool {
     let count: i64 = packages
         .select(count_star())
@@ -45,19 +126,63 @@ pub fn has_package_version(conn: &SqliteConnection, pkg: &NewPackage) -> bool {
     count > 0
 }
 
-pub fn check_removed(conn: &SqliteConnection, pkgs: &[String], pkg_format: i32) {
+/// Mark releases missing from the upstream list of all releases as removed,
+/// and releases listed again as not removed. Lists with less than 90% of the
+/// known releases are ignored, as probably truncated.
+/// Returns whether any release was marked or unmarked.
+pub fn check_removed(conn: &SqliteConnection, pkgs: &[String], pkg_format: i32) -> bool {
+    let known: i64 = packages
+        .select(count_star())
+        .filter(format.eq(pkg_format))
+        .filter(added.eq(false))
+        .get_result(conn)
+        .expect("Can't count packages from database");
+    if (pkgs.len() as i64) * 10 < known * 9 {
+        log::warn!(
+            "Not checking removed packages, only {} of {} releases listed",
+            pkgs.len(),
+            known
+        );
+        return false;
+    }
+
     let removed_packages = packages
         .select(concat_pkg())
         .distinct()
         .filter(format.eq(pkg_format))
+        .filter(added.eq(false))
         .filter(not(concat_pkg().eq_any(pkgs)))
         .order(timestamp.desc())
         .load::<String>(conn)
-        .expect(&format!("Cant check removed packages from database"));
+        .expect("Can't check removed packages from database");
 
-    for pkg in removed_packages {
-        log::warn!("{} has been removed", pkg);
+    for pkg in &removed_packages {
+        log::warn!(package = pkg.as_str(); "{} has been removed", pkg);
     }
+
+    let listed = diesel::update(
+        packages
+            .filter(format.eq(pkg_format))
+            .filter(removed.eq(true))
+            .filter(not(concat_pkg().eq_any(&removed_packages))),
+    )
+    .set(removed.eq(false))
+    .execute(conn)
+    .expect("Can't mark listed packages in database");
+    if listed > 0 {
+        log::info!("{} removed releases are listed again", listed);
+    }
+
+    let unlisted = diesel::update(
+        packages
+            .filter(format.eq(pkg_format))
+            .filter(removed.eq(false))
+            .filter(concat_pkg().eq_any(&removed_packages)),
+    )
+    .set(removed.eq(true))
+    .execute(conn)
+    .expect("Can't mark removed packages in database");
+    listed > 0 || unlisted > 0
 }
 
 /// package in string format "author/project@major.minor.patch"
@@ -138,9 +263,14 @@ fn concat_version() -> SqlLiteral<Text> {
     )
 }
 
-pub fn save_package(conn: &SqliteConnection, pkg: &NewPackage) {
+/// Insert a package release, returning false if it already exists
+pub fn save_package(conn: &SqliteConnection, pkg: &NewPackage) -> bool {
+    let repo = format!("{}/{}", pkg.author, pkg.name);
+    let version = format!("{}.{}.{}", pkg.major, pkg.minor, pkg.patch);
+
     if has_package_version(conn, pkg) {
         log::error!(
+            package = repo.as_str(), version = version.as_str(), format = pkg.format;
             "Ignored duplicate package {}/{} {}.{}.{} for {}",
             pkg.author,
             pkg.name,
@@ -149,31 +279,162 @@ pub fn save_package(conn: &SqliteConnection, pkg: &NewPackage) {
             pkg.patch,
             pkg.elm_version
         );
-        return;
+        return false;
     }
 
-    log::info!("Adding {:?}", pkg);
+    log::info!(
+        package = repo.as_str(), version = version.as_str(), format = pkg.format;
+        "Adding {:?}", pkg
+    );
     diesel::insert_into(packages::table)
         .values(pkg)
         .execute(conn)
         .expect("Can't insert package into database");
+    true
+}
+
+/// Update releases of package "author/project" at "major.minor.patch",
+/// in all formats if none is given.
+pub fn update_package(
+    conn: &SqliteConnection,
+    repo: &str,
+    version: &str,
+    pkg_format: Option<i32>,
+    changes: &PackageChanges,
+) -> usize {
+    let target = packages
+        .filter(author.concat("/").concat(name).eq(repo))
+        .filter(concat_version().eq(version));
+
+    match pkg_format {
+        Some(f) => diesel::update(target.filter(format.eq(f)))
+            .set(changes)
+            .execute(conn),
+        None => diesel::update(target).set(changes).execute(conn),
+    }
+    .expect("Can't update package in database")
+}
+
+/// Releases of a package version, in all formats unless one is given
+pub fn package_versions(
+    conn: &SqliteConnection,
+    repo: &str,
+    version: &str,
+    pkg_format: Option<i32>,
+) -> Vec<Package> {
+    let mut query = packages
+        .filter(author.concat("/").concat(name).eq(repo))
+        .filter(concat_version().eq(version))
+        .into_boxed();
+    if let Some(f) = pkg_format {
+        query = query.filter(format.eq(f));
+    }
+    query
+        .load::<Package>(conn)
+        .expect("Can't load package versions from database")
+}
+
+/// Fails when there are no changes to save
+pub fn update_package_id(
+    conn: &SqliteConnection,
+    package_id: i32,
+    changes: &PackageChanges,
+) -> QueryResult<usize> {
+    diesel::update(packages.filter(id.eq(package_id)))
+        .set(changes)
+        .execute(conn)
+}
+
+/// Save the upstream values of the fields patched by overrides
+pub fn set_original(conn: &SqliteConnection, package_id: i32, values: Option<&str>) {
+    diesel::update(packages.filter(id.eq(package_id)))
+        .set(original.eq(values))
+        .execute(conn)
+        .expect("Can't update package in database");
+}
+
+/// Releases with fields patched by overrides
+pub fn patched_packages(conn: &SqliteConnection) -> Vec<Package> {
+    packages
+        .filter(original.is_not_null())
+        .load::<Package>(conn)
+        .expect("Can't load patched packages from database")
+}
+
+/// Clear the flags set by overrides, releases added by overrides being
+/// hidden until their overrides are applied again
+pub fn reset_overrides(conn: &SqliteConnection) {
+    diesel::update(packages.filter(hidden.or(corrected).or(added).or(original.is_not_null())))
+        .set((
+            hidden.eq(added),
+            corrected.eq(false),
+            original.eq(None::<String>),
+        ))
+        .execute(conn)
+        .expect("Can't reset overrides in database");
 }
 
 pub fn last_packages(
     conn: &SqliteConnection,
-    mut filter: HashMap<String, String>,
+    filter: HashMap<String, String>,
     release: &Release,
     limit: i64,
 ) -> Vec<Package> {
+    filtered_packages(conn, filter, release)
+        .order(timestamp.desc())
+        .limit(limit)
+        .load::<Package>(conn)
+        .expect("Can't load packages from database")
+}
+
+/// Releases matching a filter inserted after the package with id `since`
+pub fn new_packages(
+    conn: &SqliteConnection,
+    filter: HashMap<String, String>,
+    release: &Release,
+    since: i32,
+) -> Vec<Package> {
+    filtered_packages(conn, filter, release)
+        .filter(id.gt(since))
+        .order(id.asc())
+        .load::<Package>(conn)
+        .expect("Can't load new packages from database")
+}
+
+pub fn last_package_id(conn: &SqliteConnection) -> i32 {
+    packages
+        .select(max(id))
+        .first::<Option<i32>>(conn)
+        .expect("Can't get last package from database")
+        .unwrap_or(0)
+}
+
+fn filtered_packages<'a>(
+    conn: &SqliteConnection,
+    mut filter: HashMap<String, String>,
+    release: &Release,
+) -> packages::BoxedQuery<'a, Sqlite> {
     let pattern = filter.remove("_search").map(|s| format!("%{}%", s));
-    let pkgs = query_packages(conn, &filter);
-    let mut query = packages.order(timestamp.desc()).limit(limit).into_boxed();
+    let transitive = filter.remove("_transitive").as_deref() == Some("1");
+    let at = filter
+        .remove("_at")
+        .and_then(|date| query::timestamp(&date).ok());
+    let mut pkgs = query_packages(conn, &filter);
+    if transitive {
+        pkgs = dependency_closure(conn, pkgs.into_iter().collect())
+            .into_iter()
+            .collect();
+    }
+    let mut query = packages.filter(hidden.eq(false)).into_boxed();
+    if let Some(at) = at {
+        query = query.filter(timestamp.le(at));
+    }
 
-    let pkg_filter = author.concat("/").concat(name).eq_any(&pkgs);
-    let search_filter = |pattern| {
+    let pkg_filter = author.concat("/").concat(name).eq_any(pkgs);
+    let search_filter = |pattern: &String| {
         (author.concat("/").concat(name))
-            .like(pattern)
-            .or(summary.like(pattern))
+            .like(pattern.clone())
+            .or(summary.like(pattern.clone()))
     };
 
     query = match (filter.is_empty(), &pattern) {
@@ -183,7 +444,7 @@ pub fn last_packages(
         (true, None) => query,
     };
 
-    query = match release {
+    match release {
         Release::Any => query,
         Release::Last => {
             query.group_by(sql::<Text>("author,name,elm_version HAVING MAX(timestamp)"))
@@ -192,11 +453,7 @@ pub fn last_packages(
         Release::Major => query.filter(minor.eq(0).and(patch.eq(0))),
         Release::Minor => query.filter(minor.ne(0).and(patch.eq(0))),
         Release::Patch => query.filter(patch.ne(0)),
-    };
-
-    query
-        .load::<Package>(conn)
-        .expect("Can't load packages from database")
+    }
 }
 
 fn query_packages(conn: &SqliteConnection, filter: &HashMap<String, String>) -> Vec<String> {
@@ -217,6 +474,74 @@ fn expand_packages(conn: &SqliteConnection, user: &String, expr: &String) -> Vec
         .collect()
 }
 
+/// Releases of a package, e.g. "elm/core", from the oldest
+pub fn package_releases(conn: &SqliteConnection, repo: &str) -> Vec<Package> {
+    packages
+        .filter(author.concat("/").concat(name).eq(repo))
+        .filter(hidden.eq(false))
+        .order(timestamp.asc())
+        .load::<Package>(conn)
+        .expect("Can't load package releases from database")
+}
+
+/// All visible releases, from the oldest
+pub fn visible_releases(conn: &SqliteConnection) -> Vec<Package> {
+    packages
+        .filter(hidden.eq(false))
+        .order(timestamp.asc())
+        .load::<Package>(conn)
+        .expect("Can't load releases from database")
+}
+
+/// Latest release of each package matching a feed filter, published at or
+/// before a timestamp when given
+pub fn latest_releases(
+    conn: &SqliteConnection,
+    filter: HashMap<String, String>,
+    at: Option<i64>,
+) -> Vec<Package> {
+    let mut query = filtered_packages(conn, filter, &Release::Any);
+    if let Some(at) = at {
+        query = query.filter(timestamp.le(at));
+    }
+    let mut releases = query
+        .order((author, name, major.desc(), minor.desc(), patch.desc()))
+        .load::<Package>(conn)
+        .expect("Can't load latest releases from database");
+    releases.dedup_by(|a, b| a.author == b.author && a.name == b.name);
+    releases
+}
+
+/// Dependencies of the latest release of a package, e.g. "elm/core"
+pub fn latest_dependencies(conn: &SqliteConnection, repo: &str) -> HashMap<String, String> {
+    packages
+        .select(dependencies)
+        .filter(author.concat("/").concat(name).eq(repo))
+        .filter(hidden.eq(false))
+        .order((major.desc(), minor.desc(), patch.desc()))
+        .first::<String>(conn)
+        .optional()
+        .expect("Can't load package dependencies from database")
+        .and_then(|deps| serde_json::from_str(&deps).ok())
+        .unwrap_or_default()
+}
+
+/// Packages and their dependencies, recursively through the dependencies
+/// of their latest releases
+pub fn dependency_closure(conn: &SqliteConnection, pkgs: BTreeSet<String>) -> BTreeSet<String> {
+    let mut closure = BTreeSet::new();
+    let mut pending: Vec<String> = pkgs.into_iter().collect();
+
+    while let Some(pkg) = pending.pop() {
+        if closure.contains(&pkg) {
+            continue;
+        }
+        pending.extend(latest_dependencies(conn, &pkg).into_keys());
+        closure.insert(pkg);
+    }
+    closure
+}
+
 pub fn author_packages(conn: &SqliteConnection, user: &String) -> Vec<String> {
     packages
         .select(name)
@@ -226,3 +551,55 @@ pub fn author_packages(conn: &SqliteConnection, user: &String) -> Vec<String> {
         .load::<String>(conn)
         .expect(&format!("Cant load {} packages from database", user))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elm::snapshot;
+
+    fn removed_releases(conn: &SqliteConnection) -> Vec<String> {
+        packages
+            .select(concat_pkg())
+            .filter(removed.eq(true))
+            .order(concat_pkg())
+            .load(conn)
+            .unwrap()
+    }
+
+    fn listed(conn: &SqliteConnection) -> Vec<String> {
+        packages
+            .select(concat_pkg())
+            .filter(format.eq(19))
+            .filter(added.eq(false))
+            .load(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn marks_unlisted_releases_as_removed() {
+        let conn = snapshot::fixtures();
+        let mut pkgs = listed(&conn);
+        let all = pkgs.clone();
+        pkgs.retain(|pkg| pkg != "elm/json@1.1.2");
+        // Ignored as less than 90% of the releases are listed
+        assert!(!check_removed(&conn, &pkgs, 19));
+        assert!(removed_releases(&conn).is_empty());
+
+        // Releases not saved yet are listed too
+        pkgs.push("elm/bytes@1.0.0".to_string());
+        assert!(check_removed(&conn, &pkgs, 19));
+        assert!(!check_removed(&conn, &pkgs, 19));
+        // Releases added by overrides are never listed
+        assert_eq!(removed_releases(&conn), vec!["elm/json@1.1.2"]);
+
+        assert!(check_removed(&conn, &all, 19));
+        assert!(removed_releases(&conn).is_empty());
+    }
+
+    #[test]
+    fn ignores_empty_lists() {
+        let conn = snapshot::fixtures();
+        check_removed(&conn, &[], 19);
+        assert!(removed_releases(&conn).is_empty());
+    }
+}
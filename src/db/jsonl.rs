@@ -0,0 +1,214 @@
+use super::models::{
+    NewPackage, NewSubscription, Package, PackageChanges, Project, Subscription, Webhook,
+};
+use super::schema::packages::dsl::*;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+/// A database row in the JSON Lines format, one per line and tagged with
+/// its table name, e.g. `{"table":"packages","id":1,...}`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "table", rename_all = "snake_case")]
+pub enum Row {
+    Packages(Package),
+    Subscriptions(Subscription),
+    Webhooks(Webhook),
+    Projects(Project),
+}
+
+pub fn export<W: Write>(conn: &SqliteConnection, mut out: W) -> Result<usize, String> {
+    let pkgs = packages
+        .order(id.asc())
+        .load::<Package>(conn)
+        .map_err(|err| format!("can't load packages from database: {}", err))?;
+
+    let subscriptions = super::subscriptions::all(conn);
+    let webhooks = super::webhooks::all(conn);
+    let projects = super::projects::all(conn);
+
+    let count = pkgs.len() + subscriptions.len() + webhooks.len() + projects.len();
+    let rows = pkgs
+        .into_iter()
+        .map(Row::Packages)
+        .chain(subscriptions.into_iter().map(Row::Subscriptions))
+        .chain(webhooks.into_iter().map(Row::Webhooks))
+        .chain(projects.into_iter().map(Row::Projects));
+    for row in rows {
+        serde_json::to_writer(&mut out, &row).map_err(|err| err.to_string())?;
+        writeln!(out).map_err(|err| err.to_string())?;
+    }
+    Ok(count)
+}
+
+/// Import rows, skipping packages releases that already exist and
+/// replacing existing subscriptions and merging webhooks by URL, filter and
+/// release kind. Imported releases keep their flags and the upstream values
+/// of fields patched by overrides.
+/// Returns the number of imported and duplicate rows.
+pub fn import<R: BufRead>(conn: &SqliteConnection, input: R) -> Result<(usize, usize), String> {
+    let mut imported = 0;
+    let mut duplicates = 0;
+
+    for (n, line) in input.lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: Row =
+            serde_json::from_str(&line).map_err(|err| format!("line {}: {}", n + 1, err))?;
+
+        match row {
+            Row::Packages(pkg) => {
+                let new_pkg = NewPackage::from(&pkg);
+                if super::has_package_version(conn, &new_pkg) {
+                    duplicates += 1;
+                } else {
+                    super::save_package(conn, &new_pkg);
+                    let flags = PackageChanges {
+                        hidden: Some(pkg.hidden),
+                        corrected: Some(pkg.corrected),
+                        removed: Some(pkg.removed),
+                        added: Some(pkg.added),
+                        ..Default::default()
+                    };
+                    for saved in super::package_versions(
+                        conn,
+                        &format!("{}/{}", pkg.author, pkg.name),
+                        &format!("{}.{}.{}", pkg.major, pkg.minor, pkg.patch),
+                        Some(pkg.format),
+                    ) {
+                        super::update_package_id(conn, saved.id, &flags)
+                            .map_err(|err| format!("can't restore package flags: {}", err))?;
+                        super::set_original(conn, saved.id, pkg.original.as_deref());
+                    }
+                    imported += 1;
+                }
+            }
+            Row::Subscriptions(subscription) => {
+                super::subscriptions::save(
+                    conn,
+                    &NewSubscription {
+                        callback: &subscription.callback,
+                        topic: &subscription.topic,
+                        secret: subscription.secret.as_deref(),
+                        expires: subscription.expires,
+                    },
+                );
+                imported += 1;
+            }
+            Row::Webhooks(webhook) => {
+                super::webhooks::restore(conn, &webhook);
+                imported += 1;
+            }
+            Row::Projects(project) => {
+                super::projects::save(conn, &project);
+                imported += 1;
+            }
+        }
+    }
+    Ok((imported, duplicates))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::NewWebhook;
+    use crate::db::{self, webhooks};
+
+    fn package<'a>(timestamp_: &'a i64) -> NewPackage<'a> {
+        NewPackage {
+            timestamp: timestamp_,
+            major: 1,
+            minor: 0,
+            patch: 0,
+            author: "author",
+            name: "project",
+            summary: "summary",
+            license: "MIT",
+            elm_version: "0.19.0 <= v < 0.20.0",
+            dependencies: "{}",
+            format: 19,
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_package_flags() {
+        let source = db::memory();
+        db::save_package(&source, &package(&1600000000));
+        let flags = PackageChanges {
+            hidden: Some(true),
+            corrected: Some(true),
+            ..Default::default()
+        };
+        db::update_package(&source, "author/project", "1.0.0", None, &flags);
+        let mut dump = Vec::new();
+        export(&source, &mut dump).unwrap();
+
+        let target = db::memory();
+        import(&target, &dump[..]).unwrap();
+        let (hidden_, corrected_, removed_) = packages
+            .select((hidden, corrected, removed))
+            .filter(author.eq("author"))
+            .first::<(bool, bool, bool)>(&target)
+            .unwrap();
+        assert!(hidden_);
+        assert!(corrected_);
+        assert!(!removed_);
+    }
+
+    #[test]
+    fn merging_webhooks_keeps_existing_ones() {
+        let conn = db::memory();
+        let local = webhooks::save(
+            &conn,
+            &NewWebhook {
+                url: "https://local.example/hook",
+                filter: "",
+                release: "any",
+                secret: "local",
+            },
+        );
+        // Another instance exported different webhooks with the same ids
+        let rows = [
+            Webhook {
+                id: local.id,
+                url: "https://remote.example/hook".to_string(),
+                filter: "elm=core".to_string(),
+                release: "any".to_string(),
+                secret: "remote".to_string(),
+            },
+            Webhook {
+                id: local.id + 1,
+                url: "https://local.example/hook".to_string(),
+                filter: "".to_string(),
+                release: "any".to_string(),
+                secret: "rotated".to_string(),
+            },
+        ];
+        let dump: String = rows
+            .into_iter()
+            .map(|webhook| serde_json::to_string(&Row::Webhooks(webhook)).unwrap() + "\n")
+            .collect();
+        import(&conn, dump.as_bytes()).unwrap();
+
+        let all: Vec<(String, String)> = webhooks::all(&conn)
+            .into_iter()
+            .map(|webhook| (webhook.url, webhook.secret))
+            .collect();
+        assert_eq!(
+            all,
+            vec![
+                (
+                    "https://local.example/hook".to_string(),
+                    "rotated".to_string()
+                ),
+                (
+                    "https://remote.example/hook".to_string(),
+                    "remote".to_string()
+                ),
+            ]
+        );
+    }
+}
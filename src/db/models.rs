@@ -1,4 +1,5 @@
-use super::schema::packages;
+use super::schema::{packages, projects, subscriptions, webhooks};
+use serde::{Deserialize, Serialize};
 
 /// Formats:
 /// 19: elm.json
@@ -10,7 +11,7 @@ use super::schema::packages;
 ///     The packages in this format are not saved for now and produce an error
 ///     in the logs because of the missing "elm-version" field.
 
-#[derive(Queryable)]
+#[derive(Queryable, Serialize, Deserialize)]
 pub struct Package {
     pub id: i32,
     pub timestamp: i64,
@@ -24,6 +25,21 @@ pub struct Package {
     pub elm_version: String,
     pub dependencies: String,
     pub format: i32,
+    /// Hidden by the overrides file
+    #[serde(default)]
+    pub hidden: bool,
+    /// Added or patched by the overrides file
+    #[serde(default)]
+    pub corrected: bool,
+    /// Missing from the upstream list of all packages
+    #[serde(default)]
+    pub removed: bool,
+    /// Added by the overrides file
+    #[serde(default)]
+    pub added: bool,
+    /// Upstream values of the fields patched by the overrides file, as JSON
+    #[serde(default)]
+    pub original: Option<String>,
 }
 
 #[derive(Insertable, Debug)]
@@ -41,3 +57,85 @@ pub struct NewPackage<'a> {
     pub dependencies: &'a str,
     pub format: i32,
 }
+
+impl<'a> From<&'a Package> for NewPackage<'a> {
+    fn from(pkg: &'a Package) -> Self {
+        NewPackage {
+            timestamp: &pkg.timestamp,
+            major: pkg.major,
+            minor: pkg.minor,
+            patch: pkg.patch,
+            author: &pkg.author,
+            name: &pkg.name,
+            summary: &pkg.summary,
+            license: &pkg.license,
+            elm_version: &pkg.elm_version,
+            dependencies: &pkg.dependencies,
+            format: pkg.format,
+        }
+    }
+}
+
+/// Fields that can be patched by the overrides file, or restored from an
+/// export
+#[derive(AsChangeset, Default)]
+#[table_name = "packages"]
+pub struct PackageChanges<'a> {
+    pub timestamp: Option<&'a i64>,
+    pub summary: Option<&'a str>,
+    pub license: Option<&'a str>,
+    pub elm_version: Option<&'a str>,
+    pub dependencies: Option<&'a str>,
+    pub hidden: Option<bool>,
+    pub corrected: Option<bool>,
+    pub removed: Option<bool>,
+    pub added: Option<bool>,
+}
+
+/// WebSub subscription of a callback URL to a feed URL (topic)
+#[derive(Queryable, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: i32,
+    pub callback: String,
+    pub topic: String,
+    pub secret: Option<String>,
+    pub expires: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "subscriptions"]
+pub struct NewSubscription<'a> {
+    pub callback: &'a str,
+    pub topic: &'a str,
+    pub secret: Option<&'a str>,
+    pub expires: i64,
+}
+
+/// A webhook called for new releases matching a filter in the feed query
+/// language, e.g. "elm=core+json&_search=parser", and a release kind.
+#[derive(Queryable, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    pub filter: String,
+    pub release: String,
+    pub secret: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhooks"]
+pub struct NewWebhook<'a> {
+    pub url: &'a str,
+    pub filter: &'a str,
+    pub release: &'a str,
+    pub secret: &'a str,
+}
+
+/// An uploaded elm.json, identified by a hash of its normalized content
+#[derive(Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "projects"]
+pub struct Project {
+    pub id: String,
+    pub elm_json: String,
+    pub created: i64,
+}
@@ -0,0 +1,27 @@
+use super::models::Project;
+use super::schema::projects::dsl::*;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+/// Save a project, unless it already exists
+pub fn save(conn: &SqliteConnection, project: &Project) {
+    diesel::insert_or_ignore_into(projects)
+        .values(project)
+        .execute(conn)
+        .expect("Can't save project into database");
+}
+
+pub fn get(conn: &SqliteConnection, project_id: &str) -> Option<Project> {
+    projects
+        .find(project_id)
+        .first::<Project>(conn)
+        .optional()
+        .expect("Can't load project from database")
+}
+
+pub fn all(conn: &SqliteConnection) -> Vec<Project> {
+    projects
+        .order(created.asc())
+        .load::<Project>(conn)
+        .expect("Can't load projects from database")
+}
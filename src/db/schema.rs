@@ -12,5 +12,38 @@ table! {
         elm_version -> Text,
         dependencies -> Text,
         format -> Integer,
+        hidden -> Bool,
+        corrected -> Bool,
+        removed -> Bool,
+        added -> Bool,
+        original -> Nullable<Text>,
+    }
+}
+
+table! {
+    subscriptions (id) {
+        id -> Integer,
+        callback -> Text,
+        topic -> Text,
+        secret -> Nullable<Text>,
+        expires -> BigInt,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Integer,
+        url -> Text,
+        filter -> Text,
+        release -> Text,
+        secret -> Text,
+    }
+}
+
+table! {
+    projects (id) {
+        id -> Text,
+        elm_json -> Text,
+        created -> BigInt,
     }
 }
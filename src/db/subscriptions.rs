@@ -0,0 +1,38 @@
+use super::models::{NewSubscription, Subscription};
+use super::schema::subscriptions::dsl::*;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+/// Create or renew a subscription of a callback to a topic
+pub fn save(conn: &SqliteConnection, subscription: &NewSubscription) {
+    diesel::replace_into(subscriptions)
+        .values(subscription)
+        .execute(conn)
+        .expect("Can't save subscription into database");
+}
+
+pub fn delete(conn: &SqliteConnection, sub_callback: &str, sub_topic: &str) -> usize {
+    diesel::delete(
+        subscriptions
+            .filter(callback.eq(sub_callback))
+            .filter(topic.eq(sub_topic)),
+    )
+    .execute(conn)
+    .expect("Can't delete subscription from database")
+}
+
+pub fn all(conn: &SqliteConnection) -> Vec<Subscription> {
+    subscriptions
+        .order(id.asc())
+        .load::<Subscription>(conn)
+        .expect("Can't load subscriptions from database")
+}
+
+/// Delete expired subscriptions and return the active ones
+pub fn active(conn: &SqliteConnection, now: i64) -> Vec<Subscription> {
+    diesel::delete(subscriptions.filter(expires.le(now)))
+        .execute(conn)
+        .expect("Can't delete expired subscriptions from database");
+
+    all(conn)
+}
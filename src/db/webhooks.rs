@@ -0,0 +1,61 @@
+use super::models::{NewWebhook, Webhook};
+use super::schema::webhooks::dsl::*;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+pub fn save(conn: &SqliteConnection, webhook: &NewWebhook) -> Webhook {
+    conn.transaction(|| {
+        diesel::insert_into(webhooks)
+            .values(webhook)
+            .execute(conn)?;
+        webhooks.order(id.desc()).first::<Webhook>(conn)
+    })
+    .expect("Can't save webhook into database")
+}
+
+/// Save a webhook from an export. Ids of different instances collide, so
+/// webhooks are matched by URL, filter and release kind: the secret of a
+/// matching webhook is replaced, otherwise a webhook is added with a new id.
+pub fn restore(conn: &SqliteConnection, webhook: &Webhook) {
+    let existing = webhooks
+        .select(id)
+        .filter(url.eq(&webhook.url))
+        .filter(filter.eq(&webhook.filter))
+        .filter(release.eq(&webhook.release))
+        .first::<i32>(conn)
+        .optional()
+        .expect("Can't load webhook from database");
+
+    match existing {
+        Some(existing) => {
+            diesel::update(webhooks.filter(id.eq(existing)))
+                .set(secret.eq(&webhook.secret))
+                .execute(conn)
+                .expect("Can't save webhook into database");
+        }
+        None => {
+            save(
+                conn,
+                &NewWebhook {
+                    url: &webhook.url,
+                    filter: &webhook.filter,
+                    release: &webhook.release,
+                    secret: &webhook.secret,
+                },
+            );
+        }
+    }
+}
+
+pub fn delete(conn: &SqliteConnection, webhook_id: i32) -> usize {
+    diesel::delete(webhooks.filter(id.eq(webhook_id)))
+        .execute(conn)
+        .expect("Can't delete webhook from database")
+}
+
+pub fn all(conn: &SqliteConnection) -> Vec<Webhook> {
+    webhooks
+        .order(id.asc())
+        .load::<Webhook>(conn)
+        .expect("Can't load webhooks from database")
+}
@@ -0,0 +1,144 @@
+use crate::db;
+use crate::elm::project;
+use crate::projects;
+use crate::query;
+use crate::release::Release;
+use crate::rss;
+use std::collections::HashMap;
+use std::fs;
+
+pub const USAGE: &str = "greenwood deps [--base-url URL] [--release any|last|first|major|minor|patch] [--output web|rss|opml] [--offline] [path/to/elm.json]";
+
+/// What the deps subcommand prints
+pub enum Output {
+    /// Web and RSS feed URLs, like elm-deps-rss
+    Urls,
+    Web,
+    Rss,
+    Opml,
+    /// The RSS feed itself, rendered from the local database
+    Offline,
+}
+
+pub struct Options {
+    pub base_url: String,
+    pub release: Release,
+    pub output: Output,
+    pub path: String,
+}
+
+impl Options {
+    /// Parse the arguments following "deps"
+    pub fn parse(args: &[&str]) -> Result<Options, String> {
+        let mut options = Options {
+            base_url: rss::base_url(),
+            release: Release::Any,
+            output: Output::Urls,
+            path: "elm.json".to_string(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match *arg {
+                "--base-url" => options.base_url = value()?.trim_end_matches('/').to_string(),
+                "--release" => options.release = value()?.parse()?,
+                "--output" => {
+                    options.output = match *value()? {
+                        "web" => Output::Web,
+                        "rss" => Output::Rss,
+                        "opml" => Output::Opml,
+                        other => return Err(format!("unknown output {}", other)),
+                    }
+                }
+                "--offline" => options.output = Output::Offline,
+                path if !path.starts_with("--") => options.path = path.to_string(),
+                other => return Err(format!("unknown option {}", other)),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn offline(&self) -> bool {
+        matches!(self.output, Output::Offline)
+    }
+}
+
+/// Print the feed URLs of the dependencies of an elm.json, or render their
+/// feed from the local database when offline.
+pub fn run(options: &Options, pool: Option<&db::Pool>) -> Result<(), String> {
+    let json =
+        fs::read_to_string(&options.path).map_err(|err| format!("{}: {}", options.path, err))?;
+    let filter = projects::filter(&project::parse(&json)?.dependencies());
+    let web_url = url(&options.base_url, web_route(&options.release), &filter);
+    let rss_url = url(&options.base_url, options.release.route(), &filter);
+
+    match options.output {
+        Output::Urls => {
+            println!("Web feed:\n{}\n\nRSS feed:\n{}", web_url, rss_url);
+        }
+        Output::Web => println!("{}", web_url),
+        Output::Rss => println!("{}", rss_url),
+        Output::Opml => print!("{}", opml(&filter, &options.release, &web_url, &rss_url)),
+        Output::Offline => {
+            let pool = pool.ok_or("offline rendering requires a database")?;
+            let conn = pool.get().map_err(|err| err.to_string())?;
+            let feed = rss::project(
+                &conn,
+                String::new(),
+                &options.path,
+                filter,
+                &options.release,
+                &rss_url,
+            );
+            println!("{}", feed);
+        }
+    }
+    Ok(())
+}
+
+/// Path of the web page of a release kind, e.g. "/last"
+fn web_route(release: &Release) -> &'static str {
+    release.route().trim_end_matches("/.rss")
+}
+
+fn url(base_url: &str, route: &str, filter: &HashMap<String, String>) -> String {
+    match query::encode(filter).as_str() {
+        "" => format!("{}{}", base_url, route),
+        q => format!("{}{}?{}", base_url, route, q),
+    }
+}
+
+fn opml(
+    filter: &HashMap<String, String>,
+    release: &Release,
+    web_url: &str,
+    rss_url: &str,
+) -> String {
+    let title = escape(&rss::channel_title(filter, release));
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head>
+    <title>{title}</title>
+  </head>
+  <body>
+    <outline type="rss" text="{title}" title="{title}" xmlUrl="{rss_url}" htmlUrl="{web_url}"/>
+  </body>
+</opml>
+"#,
+        title = title,
+        rss_url = escape(rss_url),
+        web_url = escape(web_url),
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
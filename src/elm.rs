@@ -1,6 +1,11 @@
 pub mod old_format_packages;
 pub mod packages;
+pub mod project;
+pub mod snapshot;
+pub mod solver;
+pub mod version;
 use crate::db::models::NewPackage;
+use crate::metrics;
 use serde::Deserialize;
 use std::collections::HashMap;
 
@@ -73,6 +78,7 @@ fn map_package<F>(
         };
         f(&package);
     } else {
-        log::error!("Ignoring invalid package {} {}", pkg, version);
+        metrics::ignored_package();
+        log::error!(package = pkg, version = version; "Ignoring invalid package {} {}", pkg, version);
     }
 }
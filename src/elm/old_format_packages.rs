@@ -1,5 +1,6 @@
 use crate::db;
 use crate::db::models::NewPackage;
+use crate::metrics;
 use diesel::sqlite::SqliteConnection;
 use reqwest::header::LAST_MODIFIED;
 use reqwest::Client;
@@ -12,7 +13,7 @@ pub struct OldPackage {
     pub versions: Vec<String>,
 }
 
-pub fn map<F>(f: F, conn: &SqliteConnection)
+pub fn map<F>(f: F, conn: &SqliteConnection) -> Result<(), String>
 where
     F: Fn(&NewPackage),
 {
@@ -24,8 +25,10 @@ where
         .and_then(|mut resp| resp.text())
         .map_err(|err| err.to_string())
         .and_then(|s| serde_json::from_str(&s).map_err(|err| err.to_string()))
-        .map_err(|err| log::error!("can't get old format packages: {}", err))
-        .unwrap_or(vec![]);
+        .map_err(|err| {
+            metrics::upstream_error("old-format");
+            format!("can't get old format packages: {}", err)
+        })?;
 
     for pkg in pkgs {
         // First quickly find missing packages
@@ -39,6 +42,7 @@ where
             }
         }
     }
+    Ok(())
 }
 
 fn elm_package(
@@ -65,7 +69,13 @@ fn elm_package(
         .and_then(|mut resp| resp.text())
         .map_err(|err| err.to_string())
         .and_then(|s| serde_json::from_str(&s).map_err(|err| err.to_string()))
-        .map_err(|err| log::error!("can't get {} {} elm.json: {}", name, version, err));
+        .map_err(|err| {
+            metrics::upstream_error("old-format");
+            log::error!(
+                package = name.as_str(), version = version.as_str();
+                "can't get {} {} elm.json: {}", name, version, err
+            )
+        });
 
     (elm, last_modified)
 }
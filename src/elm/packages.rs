@@ -1,10 +1,11 @@
 use crate::db;
 use crate::db::models::NewPackage;
+use crate::metrics;
 use diesel::sqlite::SqliteConnection;
 use reqwest::Client;
 use std::collections::HashMap;
 
-pub fn map<F>(f: F)
+pub fn map<F>(f: F) -> Result<(), String>
 where
     F: Fn(&NewPackage),
 {
@@ -16,8 +17,10 @@ where
         .and_then(|mut resp| resp.text())
         .map_err(|err| err.to_string())
         .and_then(|s| serde_json::from_str(&s).map_err(|err| err.to_string()))
-        .map_err(|err| log::error!("can't get all packages: {}", err))
-        .unwrap_or(HashMap::new());
+        .map_err(|err| {
+            metrics::upstream_error("all-packages");
+            format!("can't get all packages: {}", err)
+        })?;
 
     log::info!("{} packages found", pkgs.len());
 
@@ -28,9 +31,12 @@ where
             super::map_package(&f, 19, &pkg, &version, &elm, &releases.get(&version));
         }
     }
+    Ok(())
 }
 
-pub fn map_since<F>(f: F, from: i64, conn: &SqliteConnection)
+/// Returns whether releases were marked or unmarked as removed, when
+/// checking all packages
+pub fn map_since<F>(f: F, from: i64, conn: &SqliteConnection) -> Result<bool, String>
 where
     F: Fn(&NewPackage),
 {
@@ -42,12 +48,12 @@ where
         .and_then(|mut resp| resp.text())
         .map_err(|err| err.to_string())
         .and_then(|s| serde_json::from_str(&s).map_err(|err| err.to_string()))
-        .map_err(|err| log::error!("can't get packages since {}: {}", from, err))
-        .unwrap_or(Vec::new());
+        .map_err(|err| {
+            metrics::upstream_error("all-packages");
+            format!("can't get packages since {}: {}", from, err)
+        })?;
 
-    if from == 0 {
-        db::check_removed(conn, &pkgs, 19);
-    }
+    let removed_changed = from == 0 && db::check_removed(conn, &pkgs, 19);
 
     log::info!("{} new packages", pkgs.len());
 
@@ -62,6 +68,7 @@ where
             super::map_package(&f, 19, &repo, &version, &elm, &releases.get(*version));
         }
     }
+    Ok(removed_changed)
 }
 
 fn elm(client: &Client, repo: &str, version: &str) -> Result<super::Json, ()> {
@@ -77,7 +84,13 @@ fn elm(client: &Client, repo: &str, version: &str) -> Result<super::Json, ()> {
         .and_then(|mut resp| resp.text())
         .map_err(|err| err.to_string())
         .and_then(|s| serde_json::from_str(&s).map_err(|err| err.to_string()))
-        .map_err(|err| log::error!("can't get {} {} elm.json: {}", repo, version, err))
+        .map_err(|err| {
+            metrics::upstream_error("elm.json");
+            log::error!(
+                package = repo, version = version;
+                "can't get {} {} elm.json: {}", repo, version, err
+            )
+        })
 }
 
 fn releases(client: &Client, repo: &str) -> HashMap<String, i64> {
@@ -88,6 +101,9 @@ fn releases(client: &Client, repo: &str) -> HashMap<String, i64> {
         .and_then(|mut resp| resp.text())
         .map_err(|err| err.to_string())
         .and_then(|s| serde_json::from_str(&s).map_err(|err| err.to_string()))
-        .map_err(|err| log::error!("can't get {} releases: {}", repo, err))
+        .map_err(|err| {
+            metrics::upstream_error("releases.json");
+            log::error!(package = repo; "can't get {} releases: {}", repo, err)
+        })
         .unwrap_or(HashMap::new())
 }
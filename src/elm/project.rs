@@ -0,0 +1,54 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// An elm.json file of an application or a package
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ElmJson {
+    Application(Application),
+    Package(Package),
+}
+
+/// Application dependencies are pinned to exact versions
+#[derive(Deserialize)]
+pub struct Application {
+    #[serde(rename = "elm-version")]
+    pub elm_version: String,
+    pub dependencies: Dependencies,
+}
+
+#[derive(Deserialize)]
+pub struct Dependencies {
+    #[serde(default)]
+    pub direct: BTreeMap<String, String>,
+    #[serde(default)]
+    pub indirect: BTreeMap<String, String>,
+}
+
+/// Package dependencies are constraints, e.g. "1.0.0 <= v < 2.0.0"
+#[derive(Deserialize)]
+pub struct Package {
+    #[serde(rename = "elm-version")]
+    pub elm_version: String,
+    pub dependencies: BTreeMap<String, String>,
+}
+
+pub fn parse(json: &str) -> Result<ElmJson, String> {
+    serde_json::from_str(json).map_err(|err| format!("invalid elm.json: {}", err))
+}
+
+impl ElmJson {
+    /// Direct dependencies, and indirect ones for applications
+    pub fn dependencies(&self) -> BTreeSet<String> {
+        match self {
+            ElmJson::Application(app) => app
+                .dependencies
+                .direct
+                .keys()
+                .chain(app.dependencies.indirect.keys())
+                .cloned()
+                .collect(),
+            ElmJson::Package(pkg) => pkg.dependencies.keys().cloned().collect(),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::db;
+use crate::db::models::NewPackage;
+use diesel::sqlite::SqliteConnection;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Map packages from a local directory mirroring the packages website:
+///
+///     all-packages
+///     packages/{author}/{name}/releases.json
+///     packages/{author}/{name}/{version}/elm.json
+///
+/// Fails without the list of all packages, so that restores don't silently
+/// import nothing.
+pub fn map<F>(f: F, dir: &Path, conn: &SqliteConnection) -> Result<(), String>
+where
+    F: Fn(&NewPackage),
+{
+    let pkgs: HashMap<String, Vec<String>> = read_json(&dir.join("all-packages"))
+        .map_err(|err| format!("can't read snapshot packages: {}", err))?;
+
+    log::info!("{} packages found in {}", pkgs.len(), dir.display());
+
+    for (pkg, versions) in pkgs {
+        let releases = releases(dir, &pkg);
+        for version in versions {
+            if db::has_package(conn, &format!("{}@{}", pkg, version), 19) {
+                continue;
+            }
+            let elm = elm(dir, &pkg, &version);
+            super::map_package(&f, 19, &pkg, &version, &elm, &releases.get(&version));
+        }
+    }
+    Ok(())
+}
+
+fn elm(dir: &Path, repo: &str, version: &str) -> Result<super::Json, ()> {
+    let path = dir
+        .join("packages")
+        .join(repo)
+        .join(version)
+        .join("elm.json");
+    read_json(&path).map_err(|err| {
+        log::error!(
+            package = repo, version = version;
+            "can't read {} {} elm.json: {}", repo, version, err
+        )
+    })
+}
+
+fn releases(dir: &Path, repo: &str) -> HashMap<String, i64> {
+    let path = dir.join("packages").join(repo).join("releases.json");
+    read_json(&path)
+        .map_err(|err| log::error!(package = repo; "can't read {} releases: {}", repo, err))
+        .unwrap_or_default()
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    fs::read_to_string(path)
+        .map_err(|err| format!("{}: {}", path.display(), err))
+        .and_then(|s| {
+            serde_json::from_str(&s).map_err(|err| format!("{}: {}", path.display(), err))
+        })
+}
+
+/// Snapshot of a few elm packages, and of an elm/broken package without
+/// elm.json
+#[cfg(test)]
+pub fn fixtures_dir() -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/snapshot")
+}
+
+/// In-memory database seeded from the fixtures snapshot
+#[cfg(test)]
+pub fn fixtures() -> SqliteConnection {
+    let conn = db::memory();
+    let dir = fixtures_dir();
+    map(
+        |pkg| {
+            db::save_package(&conn, pkg);
+        },
+        &dir,
+        &conn,
+    )
+    .expect("Can't import fixtures");
+    conn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn maps_valid_releases() {
+        let dir = fixtures_dir();
+        let conn = db::memory();
+        let mapped = RefCell::new(Vec::new());
+        map(
+            |pkg| {
+                mapped.borrow_mut().push(format!(
+                    "{}/{} {}.{}.{} {}",
+                    pkg.author, pkg.name, pkg.major, pkg.minor, pkg.patch, pkg.timestamp
+                ))
+            },
+            &dir,
+            &conn,
+        )
+        .unwrap();
+
+        let mut mapped = mapped.into_inner();
+        mapped.sort();
+        // elm/broken has no elm.json
+        assert_eq!(
+            mapped,
+            vec![
+                "elm/core 1.0.0 1534000000",
+                "elm/core 1.0.5 1580000000",
+                "elm/http 2.0.0 1560000000",
+                "elm/json 1.1.2 1540000000",
+                "elm/json 1.1.3 1550000000",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_saved_releases() {
+        let conn = fixtures();
+        let dir = fixtures_dir();
+        let mapped = RefCell::new(0);
+        map(|_| *mapped.borrow_mut() += 1, &dir, &conn).unwrap();
+        assert_eq!(mapped.into_inner(), 0);
+    }
+
+    #[test]
+    fn fails_without_all_packages() {
+        let conn = db::memory();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/missing");
+        assert!(map(|_| (), &dir, &conn).is_err());
+    }
+}
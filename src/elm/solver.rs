@@ -0,0 +1,460 @@
+use super::project::{Application, ElmJson};
+use super::version::{Constraint, Version};
+use crate::db;
+use diesel::sqlite::SqliteConnection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// Maximum number of tried versions, as unsolvable dependency sets can take
+/// exponential time to prove.
+const MAX_STEPS: usize = 100_000;
+
+/// A release of a package that can be selected by the solver
+pub struct Candidate {
+    pub version: Version,
+    pub elm_version: Constraint,
+    pub dependencies: BTreeMap<String, Constraint>,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub solvable: bool,
+    pub error: Option<String>,
+    /// Newest mutually compatible versions, in the elm.json format
+    pub dependencies: Option<Dependencies>,
+    pub changes: Vec<Change>,
+    pub blockers: Vec<Blocker>,
+}
+
+#[derive(Serialize)]
+pub struct Dependencies {
+    pub direct: BTreeMap<String, Version>,
+    pub indirect: BTreeMap<String, Version>,
+}
+
+#[derive(Serialize)]
+pub struct Change {
+    pub package: String,
+    pub from: Option<Version>,
+    pub to: Option<Version>,
+}
+
+/// Why a package can't be upgraded to its latest release supporting the
+/// elm version: `by` requires `requires`.
+#[derive(Serialize)]
+pub struct Blocker {
+    pub package: String,
+    pub version: Version,
+    pub latest: Version,
+    pub by: String,
+    pub requires: String,
+}
+
+struct Solver<F> {
+    releases: F,
+    candidates: HashMap<String, Rc<Vec<Candidate>>>,
+    elm_version: Version,
+    steps: usize,
+}
+
+impl<F> Solver<F>
+where
+    F: FnMut(&str) -> Vec<Candidate>,
+{
+    /// Candidates of a package supporting the elm version, from the newest
+    fn candidates(&mut self, package: &str) -> Rc<Vec<Candidate>> {
+        if let Some(candidates) = self.candidates.get(package) {
+            return candidates.clone();
+        }
+        let mut candidates: Vec<Candidate> = (self.releases)(package)
+            .into_iter()
+            .filter(|candidate| candidate.elm_version.contains(&self.elm_version))
+            .collect();
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.version));
+        let candidates = Rc::new(candidates);
+        self.candidates
+            .insert(package.to_string(), candidates.clone());
+        candidates
+    }
+
+    /// Depth-first search of versions, trying the newest ones first and
+    /// packages in the order they are required.
+    fn search(
+        &mut self,
+        order: &[String],
+        assigned: &BTreeMap<String, Version>,
+        constraints: &BTreeMap<String, Vec<Constraint>>,
+    ) -> Result<Option<BTreeMap<String, Version>>, String> {
+        let package = match order.iter().find(|pkg| !assigned.contains_key(*pkg)) {
+            Some(package) => package,
+            None => return Ok(Some(assigned.clone())),
+        };
+
+        for candidate in self.candidates(package).iter() {
+            self.steps += 1;
+            if self.steps > MAX_STEPS {
+                return Err(format!("no solution found in {} steps", MAX_STEPS));
+            }
+            let allowed = constraints[package]
+                .iter()
+                .all(|constraint| constraint.contains(&candidate.version));
+            let consistent = candidate.dependencies.iter().all(|(dep, constraint)| {
+                assigned
+                    .get(dep)
+                    .map(|version| constraint.contains(version))
+                    .unwrap_or(true)
+            });
+            if !allowed || !consistent {
+                continue;
+            }
+
+            let mut order = order.to_vec();
+            let mut assigned = assigned.clone();
+            let mut constraints = constraints.clone();
+            assigned.insert(package.clone(), candidate.version);
+            for (dep, constraint) in &candidate.dependencies {
+                if !constraints.contains_key(dep) {
+                    order.push(dep.clone());
+                }
+                constraints
+                    .entry(dep.clone())
+                    .or_default()
+                    .push(*constraint);
+            }
+            if let Some(solution) = self.search(&order, &assigned, &constraints)? {
+                return Ok(Some(solution));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Constraints preventing packages of a solution to be upgraded to their
+    /// latest release
+    fn blockers(&mut self, solution: &BTreeMap<String, Version>) -> Vec<Blocker> {
+        let mut blockers = Vec::new();
+        for (package, version) in solution {
+            let candidates = self.candidates(package);
+            let latest = match candidates.first() {
+                Some(latest) if latest.version > *version => latest,
+                _ => continue,
+            };
+
+            // Packages of the solution excluding the latest release
+            for (other, other_version) in solution {
+                let other_candidates = self.candidates(other);
+                let constraint = other_candidates
+                    .iter()
+                    .find(|candidate| candidate.version == *other_version)
+                    .and_then(|candidate| candidate.dependencies.get(package));
+                if let Some(constraint) = constraint {
+                    if !constraint.contains(&latest.version) {
+                        blockers.push(Blocker {
+                            package: package.clone(),
+                            version: *version,
+                            latest: latest.version,
+                            by: format!("{} {}", other, other_version),
+                            requires: format!("{} {}", package, constraint),
+                        });
+                    }
+                }
+            }
+
+            // Dependencies of the latest release excluding the solution
+            for (dep, constraint) in &latest.dependencies {
+                if let Some(dep_version) = solution.get(dep) {
+                    if !constraint.contains(dep_version) {
+                        blockers.push(Blocker {
+                            package: package.clone(),
+                            version: *version,
+                            latest: latest.version,
+                            by: format!("{} {}", package, latest.version),
+                            requires: format!("{} {}", dep, constraint),
+                        });
+                    }
+                }
+            }
+        }
+        blockers
+    }
+}
+
+/// Compute the newest mutually compatible versions of the direct
+/// dependencies of an application and of their own dependencies, given the
+/// releases of each package.
+pub fn solve<F>(
+    direct: &[String],
+    elm_version: Version,
+    releases: F,
+) -> Result<(BTreeMap<String, Version>, Vec<Blocker>), String>
+where
+    F: FnMut(&str) -> Vec<Candidate>,
+{
+    let mut solver = Solver {
+        releases,
+        candidates: HashMap::new(),
+        elm_version,
+        steps: 0,
+    };
+    let any = Constraint {
+        lower: Version::new(0, 0, 0),
+        lower_inclusive: true,
+        upper: Version::new(i32::MAX, 0, 0),
+        upper_inclusive: false,
+    };
+    let constraints = direct.iter().map(|pkg| (pkg.clone(), vec![any])).collect();
+
+    match solver.search(direct, &BTreeMap::new(), &constraints)? {
+        Some(solution) => {
+            let blockers = solver.blockers(&solution);
+            Ok((solution, blockers))
+        }
+        None => Err("no mutually compatible versions".to_string()),
+    }
+}
+
+/// Candidates from the releases of a package in the database
+fn candidates(conn: &SqliteConnection, package: &str) -> Vec<Candidate> {
+    db::package_releases(conn, package)
+        .into_iter()
+        .filter_map(|release| {
+            let dependencies: BTreeMap<String, String> =
+                serde_json::from_str(&release.dependencies).ok()?;
+            Some(Candidate {
+                version: Version::new(release.major, release.minor, release.patch),
+                elm_version: release.elm_version.parse().ok()?,
+                dependencies: dependencies
+                    .into_iter()
+                    .map(|(dep, constraint)| Some((dep, constraint.parse().ok()?)))
+                    .collect::<Option<_>>()?,
+            })
+        })
+        .collect()
+}
+
+/// Check whether the dependencies of an application can be upgraded
+pub fn report(conn: &SqliteConnection, elm_json: &ElmJson) -> Result<Report, String> {
+    let app: &Application = match elm_json {
+        ElmJson::Application(app) => app,
+        ElmJson::Package(_) => return Err("only applications pin versions".to_string()),
+    };
+    let elm_version: Version = app.elm_version.parse()?;
+    let direct: Vec<String> = app.dependencies.direct.keys().cloned().collect();
+
+    let (solution, blockers) =
+        match solve(&direct, elm_version, |package| candidates(conn, package)) {
+            Ok(solved) => solved,
+            Err(err) => {
+                return Ok(Report {
+                    solvable: false,
+                    error: Some(err),
+                    dependencies: None,
+                    changes: Vec::new(),
+                    blockers: Vec::new(),
+                })
+            }
+        };
+
+    let pins: BTreeMap<&String, Option<Version>> = app
+        .dependencies
+        .direct
+        .iter()
+        .chain(app.dependencies.indirect.iter())
+        .map(|(pkg, pin)| (pkg, pin.parse().ok()))
+        .collect();
+    let mut changes: Vec<Change> = pins
+        .iter()
+        .filter(|(pkg, pin)| solution.get(**pkg) != pin.as_ref())
+        .map(|(pkg, pin)| Change {
+            package: pkg.to_string(),
+            from: *pin,
+            to: solution.get(*pkg).copied(),
+        })
+        .collect();
+    changes.extend(
+        solution
+            .iter()
+            .filter(|(pkg, _)| !pins.contains_key(pkg))
+            .map(|(pkg, version)| Change {
+                package: pkg.clone(),
+                from: None,
+                to: Some(*version),
+            }),
+    );
+    changes.sort_by(|a, b| a.package.cmp(&b.package));
+
+    let (direct, indirect) = solution
+        .into_iter()
+        .partition(|(pkg, _)| app.dependencies.direct.contains_key(pkg));
+    Ok(Report {
+        solvable: true,
+        error: None,
+        dependencies: Some(Dependencies { direct, indirect }),
+        changes,
+        blockers,
+    })
+}
+
+/// Human readable report of changes and blockers
+pub fn text(report: &Report) -> String {
+    if let Some(err) = &report.error {
+        return format!("Can't solve dependencies: {}", err);
+    }
+    let show = |v: &Option<Version>| v.map(|v| v.to_string()).unwrap_or("-".to_string());
+    let mut lines: Vec<String> = report
+        .changes
+        .iter()
+        .map(|change| {
+            format!(
+                "{:40} {:>10} -> {}",
+                change.package,
+                show(&change.from),
+                show(&change.to)
+            )
+        })
+        .collect();
+    if report.changes.is_empty() {
+        lines.push("Dependencies are up to date".to_string());
+    }
+    if !report.blockers.is_empty() {
+        lines.push("\nBlockers:".to_string());
+    }
+    for blocker in &report.blockers {
+        lines.push(format!(
+            "{} {} can't be upgraded to {}: {} requires {}",
+            blocker.package, blocker.version, blocker.latest, blocker.by, blocker.requires
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elm::{project, snapshot};
+
+    fn candidate(version: &str, dependencies: &[(&str, &str)]) -> Candidate {
+        Candidate {
+            version: version.parse().unwrap(),
+            elm_version: "0.19.0 <= v < 0.20.0".parse().unwrap(),
+            dependencies: dependencies
+                .iter()
+                .map(|(dep, constraint)| (dep.to_string(), constraint.parse().unwrap()))
+                .collect(),
+        }
+    }
+
+    fn elm_json(direct: &str, indirect: &str) -> ElmJson {
+        project::parse(&format!(
+            r#"{{ "type": "application", "elm-version": "0.19.1",
+                  "dependencies": {{ "direct": {}, "indirect": {} }} }}"#,
+            direct, indirect
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn solves_newest_compatible_versions() {
+        let conn = snapshot::fixtures();
+        let report = report(
+            &conn,
+            &elm_json(
+                r#"{ "elm/http": "2.0.0" }"#,
+                r#"{ "elm/core": "1.0.0", "elm/json": "1.1.2" }"#,
+            ),
+        )
+        .unwrap();
+
+        assert!(report.solvable);
+        let dependencies = report.dependencies.unwrap();
+        let versions = |deps: &BTreeMap<String, Version>| {
+            deps.iter()
+                .map(|(pkg, version)| format!("{} {}", pkg, version))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions(&dependencies.direct), vec!["elm/http 2.0.0"]);
+        assert_eq!(
+            versions(&dependencies.indirect),
+            vec!["elm/core 1.0.5", "elm/json 1.1.3"]
+        );
+        let changes: Vec<String> = report
+            .changes
+            .iter()
+            .map(|change| {
+                format!(
+                    "{} {} -> {}",
+                    change.package,
+                    change.from.unwrap(),
+                    change.to.unwrap()
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            vec!["elm/core 1.0.0 -> 1.0.5", "elm/json 1.1.2 -> 1.1.3"]
+        );
+    }
+
+    #[test]
+    fn reports_blockers() {
+        let (solution, blockers) = solve(
+            &["app/a".to_string(), "app/b".to_string()],
+            Version::new(0, 19, 1),
+            |package| match package {
+                "app/a" => vec![candidate("1.0.0", &[("app/b", "1.0.0 <= v < 2.0.0")])],
+                "app/b" => vec![candidate("1.0.0", &[]), candidate("2.0.0", &[])],
+                _ => vec![],
+            },
+        )
+        .unwrap();
+
+        assert_eq!(solution["app/b"], Version::new(1, 0, 0));
+        assert_eq!(blockers.len(), 1);
+        assert_eq!(blockers[0].package, "app/b");
+        assert_eq!(blockers[0].by, "app/a 1.0.0");
+    }
+
+    #[test]
+    fn fails_on_conflicts() {
+        let result = solve(
+            &["app/a".to_string(), "app/c".to_string()],
+            Version::new(0, 19, 1),
+            |package| match package {
+                "app/a" => vec![candidate("1.0.0", &[("app/b", "1.0.0 <= v < 2.0.0")])],
+                "app/b" => vec![candidate("1.0.0", &[]), candidate("2.0.0", &[])],
+                "app/c" => vec![candidate("1.0.0", &[("app/b", "2.0.0 <= v < 3.0.0")])],
+                _ => vec![],
+            },
+        );
+        assert_eq!(result.err().unwrap(), "no mutually compatible versions");
+    }
+
+    #[test]
+    fn ignores_releases_without_elm_version_support() {
+        let result = solve(&["app/a".to_string()], Version::new(0, 18, 0), |_| {
+            vec![candidate("1.0.0", &[])]
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn gives_up_after_max_steps() {
+        // 10^6 combinations of 6 packages are tried before "app/z", which
+        // requires a missing version of "app/p0"
+        let mut direct: Vec<String> = (0..6).map(|n| format!("app/p{}", n)).collect();
+        direct.push("app/z".to_string());
+        let result = solve(&direct, Version::new(0, 19, 1), |package| {
+            if package == "app/z" {
+                vec![candidate("1.0.0", &[("app/p0", "100.0.0 <= v < 101.0.0")])]
+            } else {
+                (0..10)
+                    .map(|n| candidate(&format!("1.0.{}", n), &[]))
+                    .collect()
+            }
+        });
+        assert_eq!(
+            result.err().unwrap(),
+            format!("no solution found in {} steps", MAX_STEPS)
+        );
+    }
+}
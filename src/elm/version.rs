@@ -0,0 +1,184 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A semantic version, e.g. "1.0.2"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: i32,
+    pub minor: i32,
+    pub patch: i32,
+}
+
+/// A version range of elm.json files, e.g. "1.0.0 <= v < 2.0.0"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Constraint {
+    pub lower: Version,
+    pub lower_inclusive: bool,
+    pub upper: Version,
+    pub upper_inclusive: bool,
+}
+
+impl Version {
+    pub fn new(major: i32, minor: i32, patch: i32) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Version, String> {
+        let fields: Vec<Option<i32>> = s.trim().split('.').map(|n| n.parse().ok()).collect();
+        match fields[..] {
+            [Some(major), Some(minor), Some(patch)] => Ok(Version::new(major, minor, patch)),
+            _ => Err(format!("invalid version {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Constraint {
+    pub fn contains(&self, version: &Version) -> bool {
+        let above = if self.lower_inclusive {
+            self.lower <= *version
+        } else {
+            self.lower < *version
+        };
+        let below = if self.upper_inclusive {
+            *version <= self.upper
+        } else {
+            *version < self.upper
+        };
+        above && below
+    }
+
+    /// Whether some versions are in both constraints
+    pub fn intersects(&self, other: &Constraint) -> bool {
+        let (lower, lower_inclusive) = if self.lower == other.lower {
+            (self.lower, self.lower_inclusive && other.lower_inclusive)
+        } else {
+            std::cmp::max(
+                (self.lower, self.lower_inclusive),
+                (other.lower, other.lower_inclusive),
+            )
+        };
+        let (upper, upper_inclusive) = if self.upper == other.upper {
+            (self.upper, self.upper_inclusive && other.upper_inclusive)
+        } else {
+            std::cmp::min(
+                (self.upper, self.upper_inclusive),
+                (other.upper, other.upper_inclusive),
+            )
+        };
+        lower < upper || (lower == upper && lower_inclusive && upper_inclusive)
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Constraint, String> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let inclusive = |op| match op {
+            "<=" => Some(true),
+            "<" => Some(false),
+            _ => None,
+        };
+        match fields[..] {
+            [lower, lower_op, "v", upper_op, upper] => {
+                match (inclusive(lower_op), inclusive(upper_op)) {
+                    (Some(lower_inclusive), Some(upper_inclusive)) => Ok(Constraint {
+                        lower: lower.parse()?,
+                        lower_inclusive,
+                        upper: upper.parse()?,
+                        upper_inclusive,
+                    }),
+                    _ => Err(format!("invalid constraint {}", s)),
+                }
+            }
+            _ => Err(format!("invalid constraint {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} v {} {}",
+            self.lower,
+            if self.lower_inclusive { "<=" } else { "<" },
+            if self.upper_inclusive { "<=" } else { "<" },
+            self.upper
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraint(s: &str) -> Constraint {
+        s.parse().unwrap()
+    }
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_constraints() {
+        let c = constraint("1.0.0 <= v < 2.0.0");
+        assert_eq!(c.to_string(), "1.0.0 <= v < 2.0.0");
+        assert!(c.lower_inclusive && !c.upper_inclusive);
+        assert!("1.0.0 <= v".parse::<Constraint>().is_err());
+        assert!("1.0.0 >= v < 2.0.0".parse::<Constraint>().is_err());
+        assert!("1.0 <= v < 2.0.0".parse::<Constraint>().is_err());
+    }
+
+    #[test]
+    fn contains_versions_within_bounds() {
+        let c = constraint("1.0.0 <= v < 2.0.0");
+        assert!(c.contains(&version("1.0.0")));
+        assert!(c.contains(&version("1.9.9")));
+        assert!(!c.contains(&version("2.0.0")));
+        assert!(!c.contains(&version("0.9.9")));
+
+        let c = constraint("1.0.0 < v <= 2.0.0");
+        assert!(!c.contains(&version("1.0.0")));
+        assert!(c.contains(&version("1.0.1")));
+        assert!(c.contains(&version("2.0.0")));
+    }
+
+    #[test]
+    fn intersects_overlapping_constraints() {
+        let c = constraint("1.0.0 <= v < 2.0.0");
+        assert!(c.intersects(&c));
+        assert!(c.intersects(&constraint("1.5.0 <= v < 3.0.0")));
+        assert!(c.intersects(&constraint("0.1.0 <= v < 1.0.1")));
+        assert!(c.intersects(&constraint("1.2.0 <= v <= 1.2.0")));
+        assert!(!c.intersects(&constraint("2.0.0 <= v < 3.0.0")));
+        assert!(!c.intersects(&constraint("0.1.0 <= v < 1.0.0")));
+        assert!(!c.intersects(&constraint("1.0.0 < v < 1.0.0")));
+        assert!(constraint("1.0.0 <= v <= 2.0.0").intersects(&constraint("2.0.0 <= v < 3.0.0")));
+        assert!(!constraint("1.0.0 <= v <= 2.0.0").intersects(&constraint("2.0.0 < v < 3.0.0")));
+        // Applications pin an exact elm version
+        assert!(constraint("0.19.1 <= v <= 0.19.1").intersects(&constraint("0.19.0 <= v < 0.20.0")));
+    }
+}
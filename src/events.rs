@@ -0,0 +1,48 @@
+use crate::api::PackageRelease;
+use crate::db;
+use crate::release::Release;
+use futures::{stream, Stream};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::timer::Interval;
+use warp::sse::ServerSentEvent;
+
+/// Delay between checks of new releases for each connected client
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Stream of releases matching a filter and a release kind, inserted after
+/// the package with id `since`, as server-sent events identified by the
+/// package id so that clients can resume with a Last-Event-ID header.
+pub fn releases(
+    pool: db::Pool,
+    filter: HashMap<String, String>,
+    release: Release,
+    since: i32,
+) -> impl Stream<Item = impl ServerSentEvent, Error = tokio::timer::Error> + Send {
+    let mut last_id = since;
+
+    Interval::new(Instant::now(), POLL_INTERVAL)
+        .map(move |_| {
+            let packages = match pool.get() {
+                Ok(conn) => db::new_packages(&conn, filter.clone(), &release, last_id),
+                Err(err) => {
+                    log::warn!("Can't get database connection: {}", err);
+                    Vec::new()
+                }
+            };
+            if let Some(package) = packages.last() {
+                last_id = package.id;
+            }
+            stream::iter_ok(packages)
+        })
+        .flatten()
+        .map(|package| {
+            let data = serde_json::to_string(&PackageRelease::from(&package))
+                .expect("Can't serialize release");
+            (
+                warp::sse::id(package.id as u64),
+                warp::sse::event("release"),
+                warp::sse::data(data),
+            )
+        })
+}
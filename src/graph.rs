@@ -0,0 +1,178 @@
+use crate::db;
+use crate::db::models::Package;
+use crate::elm::version::Version;
+use crate::projects;
+use crate::query;
+use chrono::{TimeZone, Utc};
+use diesel::sqlite::SqliteConnection;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::str::FromStr;
+
+pub const USAGE: &str = "greenwood graph [--format dot|json] [--at DATE] [query, e.g. elm=*]";
+
+pub enum Format {
+    /// Graphviz digraph
+    Dot,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format {}", s)),
+        }
+    }
+}
+
+/// Dependencies between the latest releases of packages
+#[derive(Serialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Serialize)]
+pub struct Node {
+    pub id: String,
+    pub version: Option<Version>,
+    pub published: Option<String>,
+    /// Whether the package matches the filter, as opposed to only being a
+    /// dependency of one
+    pub selected: bool,
+}
+
+#[derive(Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub constraint: String,
+}
+
+/// Dependency graph of the latest releases of packages matching a feed
+/// filter, published at or before a timestamp when given. Dependencies
+/// outside of the filter are nodes too, without their own edges.
+pub fn build(conn: &SqliteConnection, filter: HashMap<String, String>, at: Option<i64>) -> Graph {
+    let selected = db::latest_releases(conn, filter, at);
+    let mut edges = Vec::new();
+    for package in &selected {
+        let from = format!("{}/{}", package.author, package.name);
+        let dependencies: BTreeMap<String, String> =
+            serde_json::from_str(&package.dependencies).unwrap_or_default();
+        for (to, constraint) in dependencies {
+            edges.push(Edge {
+                from: from.clone(),
+                to,
+                constraint,
+            });
+        }
+    }
+
+    let mut nodes: Vec<Node> = selected.iter().map(|pkg| node(pkg, true)).collect();
+    let known: BTreeSet<&String> = nodes.iter().map(|node| &node.id).collect();
+    let missing: BTreeSet<String> = edges
+        .iter()
+        .filter(|edge| !known.contains(&edge.to))
+        .map(|edge| edge.to.clone())
+        .collect();
+    if !missing.is_empty() {
+        let found = db::latest_releases(conn, projects::filter(&missing), at);
+        let mut dependencies: Vec<Node> = found.iter().map(|pkg| node(pkg, false)).collect();
+        // Dependencies removed upstream or published after the date
+        for id in &missing {
+            if !dependencies.iter().any(|node| &node.id == id) {
+                dependencies.push(Node {
+                    id: id.clone(),
+                    version: None,
+                    published: None,
+                    selected: false,
+                });
+            }
+        }
+        nodes.extend(dependencies);
+    }
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    Graph { nodes, edges }
+}
+
+fn node(package: &Package, selected: bool) -> Node {
+    Node {
+        id: format!("{}/{}", package.author, package.name),
+        version: Some(Version::new(package.major, package.minor, package.patch)),
+        published: Some(
+            Utc.timestamp_opt(package.timestamp, 0)
+                .unwrap()
+                .to_rfc3339(),
+        ),
+        selected,
+    }
+}
+
+/// Graphviz digraph, dependencies outside of the filter being dashed and
+/// edges labeled by constraints
+pub fn dot(graph: &Graph) -> String {
+    let mut lines = vec!["digraph dependencies {".to_string()];
+    for node in &graph.nodes {
+        let label = match node.version {
+            Some(version) => format!("{}\\n{}", node.id, version),
+            None => node.id.clone(),
+        };
+        lines.push(format!(
+            "  \"{}\" [label=\"{}\"{}];",
+            escape(&node.id),
+            escape(&label),
+            if node.selected { "" } else { ", style=dashed" }
+        ));
+    }
+    for edge in &graph.edges {
+        lines.push(format!(
+            "  \"{}\" -> \"{}\" [label=\"{}\"];",
+            escape(&edge.from),
+            escape(&edge.to),
+            escape(&edge.constraint)
+        ));
+    }
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+/// Escape quotes of DOT strings, keeping "\n" line breaks of labels
+fn escape(s: &str) -> String {
+    s.replace('"', "\\\"")
+}
+
+pub struct Options {
+    pub format: Format,
+    pub at: Option<i64>,
+    pub filter: HashMap<String, String>,
+}
+
+impl Options {
+    /// Parse the arguments following "graph"
+    pub fn parse(args: &[&str]) -> Result<Options, String> {
+        let mut options = Options {
+            format: Format::Dot,
+            at: None,
+            filter: HashMap::new(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match *arg {
+                "--format" => options.format = value()?.parse()?,
+                "--at" => options.at = Some(query::timestamp(value()?)?),
+                filter if !filter.starts_with("--") => options.filter = query::decode(filter)?,
+                other => return Err(format!("unknown option {}", other)),
+            }
+        }
+        Ok(options)
+    }
+}
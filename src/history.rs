@@ -0,0 +1,123 @@
+use crate::api::PackageRelease;
+use crate::db::models::Package;
+use crate::elm::version::Version;
+use chrono::{TimeZone, Utc};
+use serde::Serialize;
+
+/// Every release of a package, from the oldest
+#[derive(Serialize)]
+pub struct History<'a> {
+    pub package: String,
+    pub releases: Vec<Entry<'a>>,
+    /// Consecutive releases supporting the same elm versions
+    pub elm_versions: Vec<Support>,
+    pub license_changes: Vec<LicenseChange>,
+}
+
+#[derive(Serialize)]
+pub struct Entry<'a> {
+    #[serde(flatten)]
+    pub release: PackageRelease<'a>,
+    /// 19 for elm.json, 14 and 15 for elm-package.json
+    pub format: i32,
+    /// Seconds since the previous release
+    pub since_previous: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct Support {
+    pub elm_version: String,
+    pub first: Version,
+    pub last: Version,
+    pub since: String,
+}
+
+#[derive(Serialize)]
+pub struct LicenseChange {
+    pub version: Version,
+    pub from: String,
+    pub to: String,
+    pub published: String,
+}
+
+/// History of the releases of a package, ordered by publication
+pub fn history<'a>(repo: &str, releases: &'a [Package]) -> History<'a> {
+    let mut elm_versions: Vec<Support> = Vec::new();
+    let mut license_changes = Vec::new();
+    let mut previous: Option<&Package> = None;
+
+    for package in releases {
+        let version = version(package);
+        match elm_versions.last_mut() {
+            Some(support) if support.elm_version == package.elm_version => support.last = version,
+            _ => elm_versions.push(Support {
+                elm_version: package.elm_version.clone(),
+                first: version,
+                last: version,
+                since: published(package),
+            }),
+        }
+        if let Some(previous) = previous.filter(|p| p.license != package.license) {
+            license_changes.push(LicenseChange {
+                version,
+                from: previous.license.clone(),
+                to: package.license.clone(),
+                published: published(package),
+            });
+        }
+        previous = Some(package);
+    }
+
+    History {
+        package: repo.to_string(),
+        releases: releases
+            .iter()
+            .enumerate()
+            .map(|(i, package)| Entry {
+                release: package.into(),
+                format: package.format,
+                since_previous: i
+                    .checked_sub(1)
+                    .map(|i| package.timestamp - releases[i].timestamp),
+            })
+            .collect(),
+        elm_versions,
+        license_changes,
+    }
+}
+
+/// Changes since the previous release, e.g. "42 days after 1.0.4"
+pub fn notes(previous: Option<&Package>, package: &Package) -> Vec<String> {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return vec!["first release".to_string()],
+    };
+    let mut notes = vec![format!(
+        "{} days after {}",
+        (package.timestamp - previous.timestamp) / 86400,
+        version(previous)
+    )];
+    if previous.elm_version != package.elm_version {
+        notes.push(format!(
+            "elm version changed from {} to {}",
+            previous.elm_version, package.elm_version
+        ));
+    }
+    if previous.license != package.license {
+        notes.push(format!(
+            "license changed from {} to {}",
+            previous.license, package.license
+        ));
+    }
+    notes
+}
+
+fn version(package: &Package) -> Version {
+    Version::new(package.major, package.minor, package.patch)
+}
+
+fn published(package: &Package) -> String {
+    Utc.timestamp_opt(package.timestamp, 0)
+        .unwrap()
+        .to_rfc3339()
+}
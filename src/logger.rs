@@ -0,0 +1,150 @@
+use chrono::{SecondsFormat, Utc};
+use log::kv::{Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value as Json};
+use std::env;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+use syslog::{Facility, Formatter3164, LoggerBackend};
+
+type Syslog = syslog::Logger<LoggerBackend, String, Formatter3164>;
+
+/// Where log records are written, selected with the LOG environment
+/// variable: "syslog" (default), "stderr" or "json" lines on stderr.
+enum Sink {
+    Syslog(Mutex<Syslog>),
+    Stderr,
+    Json,
+}
+
+struct Logger {
+    sink: Sink,
+}
+
+/// Initialize logging from the LOG and LOG_LEVEL environment variables.
+/// Structured fields of log records (package, version, route...) are
+/// appended to syslog and stderr messages and added to JSON objects.
+pub fn init() -> Result<(), String> {
+    let level = match env::var("LOG_LEVEL") {
+        Ok(level) => {
+            LevelFilter::from_str(&level).map_err(|_| format!("invalid LOG_LEVEL {}", level))?
+        }
+        Err(_) => LevelFilter::Info,
+    };
+
+    let (sink, fallback) = match env::var("LOG").as_ref().map(String::as_str) {
+        Ok("syslog") | Err(_) => match syslog() {
+            Ok(logger) => (Sink::Syslog(Mutex::new(logger)), None),
+            Err(err) => (Sink::Stderr, Some(err)),
+        },
+        Ok("stderr") => (Sink::Stderr, None),
+        Ok("json") => (Sink::Json, None),
+        Ok(other) => return Err(format!("invalid LOG {}", other)),
+    };
+
+    log::set_boxed_logger(Box::new(Logger { sink })).map_err(|err| err.to_string())?;
+    log::set_max_level(level);
+
+    if let Some(err) = fallback {
+        log::warn!("Can't connect to syslog, logging to stderr: {}", err);
+    }
+    Ok(())
+}
+
+fn syslog() -> Result<Syslog, String> {
+    let formatter = Formatter3164 {
+        facility: Facility::LOG_USER,
+        hostname: None,
+        process: "greenwood".to_string(),
+        pid: std::process::id() as i32,
+    };
+    syslog::unix(formatter).map_err(|err| err.to_string())
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut fields = Fields(Vec::new());
+        let _ = record.key_values().visit(&mut fields);
+
+        match &self.sink {
+            Sink::Syslog(logger) => {
+                let message = format!("{}{}", record.args(), fields.text());
+                let mut logger = logger.lock().unwrap();
+                let _ = match record.level() {
+                    log::Level::Error => logger.err(message),
+                    log::Level::Warn => logger.warning(message),
+                    log::Level::Info => logger.info(message),
+                    log::Level::Debug | log::Level::Trace => logger.debug(message),
+                };
+            }
+            Sink::Stderr => {
+                let _ = writeln!(
+                    io::stderr().lock(),
+                    "{} {:5} {}{}",
+                    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                    record.level(),
+                    record.args(),
+                    fields.text()
+                );
+            }
+            Sink::Json => {
+                let mut object = Map::new();
+                object.insert(
+                    "time".to_string(),
+                    Json::from(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+                );
+                object.insert("level".to_string(), Json::from(record.level().as_str()));
+                object.insert("target".to_string(), Json::from(record.target()));
+                object.insert("message".to_string(), Json::from(record.args().to_string()));
+                for (key, value) in fields.0 {
+                    object.insert(key, value);
+                }
+                let _ = writeln!(io::stderr().lock(), "{}", Json::Object(object));
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+struct Fields(Vec<(String, Json)>);
+
+impl Fields {
+    /// " key=value key=value" for text sinks
+    fn text(&self) -> String {
+        self.0
+            .iter()
+            .map(|(key, value)| match value {
+                Json::String(s) => format!(" {}={}", key, s),
+                _ => format!(" {}={}", key, value),
+            })
+            .collect()
+    }
+}
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let json = if let Some(n) = value.to_i64() {
+            Json::from(n)
+        } else if let Some(n) = value.to_f64() {
+            Json::from(n)
+        } else if let Some(b) = value.to_bool() {
+            Json::from(b)
+        } else {
+            Json::from(value.to_string())
+        };
+        self.0.push((key.to_string(), json));
+        Ok(())
+    }
+}
@@ -1,60 +1,299 @@
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 extern crate dotenv;
 
+mod api;
+mod authors;
+mod cache;
+mod compress;
 mod db;
+mod deps;
 mod elm;
+mod events;
+mod graph;
+mod history;
+mod logger;
+mod metrics;
+mod outdated;
+mod overrides;
+mod projects;
+mod query;
 mod release;
 mod rss;
+mod stale;
+mod stats;
+mod status;
+mod webhooks;
+mod websub;
 
+use cache::Cache;
+use chrono::{TimeZone, Utc};
 use db::models::*;
 use dotenv::dotenv;
 use release::Release;
+use status::Status;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process;
+use std::sync::mpsc;
 use std::thread;
-use std::time::Duration;
-use syslog::Facility;
+use std::time::{Duration, Instant, SystemTime};
 use warp::filters::BoxedFilter;
+use warp::http::{Response, StatusCode};
 use warp::reply::Reply;
-use warp::Filter;
+use warp::{Buf, Filter};
 
 pub enum Check {
     FromStart,
     SinceLast,
 }
 
-fn main() -> syslog::Result<()> {
-    syslog::init(Facility::LOG_USER, log::LevelFilter::Info, None)?;
-
+fn main() {
     dotenv().ok();
+    if let Err(err) = logger::init() {
+        eprintln!("Can't initialize logging: {}", err);
+        process::exit(1);
+    }
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    // Feed URLs of an elm.json don't need a database
+    if let ["deps", options @ ..] = &args[..] {
+        return deps(options);
+    }
+
+    let pool = db::pool();
+    migrate_database(&pool);
+
+    match args[..] {
+        [] => serve(pool),
+        ["import", path] if Path::new(path).is_dir() => import_snapshot(&pool, Path::new(path)),
+        ["import", path] => import_rows(&pool, path),
+        ["export"] => export_rows(&pool, "-"),
+        ["export", path] => export_rows(&pool, path),
+        ["outdated", ref options @ ..] => outdated(&pool, options),
+        ["graph", ref options @ ..] => graph(&pool, options),
+        ["solve", path] => solve(&pool, path, false),
+        ["solve", "--json", path] => solve(&pool, path, true),
+        _ => usage(),
+    }
+}
+
+/// Report outdated dependencies of an elm.json, exiting with an error when
+/// the threshold is exceeded, e.g. in continuous integration.
+fn outdated(pool: &db::Pool, args: &[&str]) {
+    let options = match outdated::Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\nUsage: {}", err, outdated::USAGE);
+            process::exit(1);
+        }
+    };
+    let elm_json = std::fs::read_to_string(&options.path)
+        .map_err(|err| format!("{}: {}", options.path, err))
+        .and_then(|json| elm::project::parse(&json));
+    let elm_json = match elm_json {
+        Ok(elm_json) => elm_json,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    let conn = pool.get().expect("Can't get database connection");
+    let report = outdated::report(&conn, &elm_json, &options.threshold);
+    if options.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Can't serialize report")
+        );
+    } else {
+        println!("{}", outdated::text(&report));
+    }
+    if report.exceeded {
+        process::exit(1);
+    }
+}
+
+/// Compute the newest mutually compatible dependencies of an application
+/// and what prevents upgrading further.
+fn solve(pool: &db::Pool, path: &str, json: bool) {
+    let conn = pool.get().expect("Can't get database connection");
+    let report = std::fs::read_to_string(path)
+        .map_err(|err| format!("{}: {}", path, err))
+        .and_then(|json| elm::project::parse(&json))
+        .and_then(|elm_json| elm::solver::report(&conn, &elm_json));
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Can't serialize report")
+        );
+    } else {
+        println!("{}", elm::solver::text(&report));
+    }
+    if !report.solvable {
+        process::exit(1);
+    }
+}
+
+/// Print the dependency graph of packages matching a feed query
+fn graph(pool: &db::Pool, args: &[&str]) {
+    let options = match graph::Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\nUsage: {}", err, graph::USAGE);
+            process::exit(1);
+        }
+    };
+
+    let conn = pool.get().expect("Can't get database connection");
+    let graph = graph::build(&conn, options.filter, options.at);
+    match options.format {
+        graph::Format::Dot => print!("{}", graph::dot(&graph)),
+        graph::Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&graph).expect("Can't serialize graph")
+        ),
+    }
+}
+
+/// Apply embedded migrations, so that deployments don't need the diesel CLI.
+fn migrate_database(pool: &db::Pool) {
+    let conn = pool.get().expect("Can't get database connection");
+    if let Err(err) = db::migrate(&conn) {
+        log::error!("Can't migrate database: {}", err);
+        eprintln!("Can't migrate database: {}", err);
+        process::exit(1);
+    }
+}
+
+fn usage() {
+    eprintln!("Usage: greenwood [import <snapshot directory|file.jsonl>|export [file.jsonl]]");
+    eprintln!("       {}", deps::USAGE);
+    eprintln!("       {}", outdated::USAGE);
+    eprintln!("       greenwood solve [--json] path/to/elm.json");
+    eprintln!("       {}", graph::USAGE);
+    process::exit(1);
+}
+
+/// Print feed URLs of the dependencies of an elm.json, or render their
+/// feed from the local database.
+fn deps(args: &[&str]) {
+    let options = match deps::Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\nUsage: {}", err, deps::USAGE);
+            process::exit(1);
+        }
+    };
+    let pool = if options.offline() {
+        let pool = db::pool();
+        migrate_database(&pool);
+        Some(pool)
+    } else {
+        None
+    };
+
+    if let Err(err) = deps::run(&options, pool.as_ref()) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn serve(pool: db::Pool) {
     let www_root = env::var("WWW_ROOT").unwrap_or("./web/static".to_string());
     log::info!("Serving files from {}", www_root);
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     log::info!("Using {} database", db_url);
+    let overrides = env::var("OVERRIDES").ok();
+    if let Some(path) = &overrides {
+        log::info!("Using {} overrides", path);
+    }
+    let admin_token = env::var("ADMIN_TOKEN").ok();
+    if admin_token.is_none() {
+        log::info!("ADMIN_TOKEN is not set, admin API is disabled");
+    }
+    let cache = Cache::default();
+    let status = Status::default();
+    let mut modified = None;
+    apply_overrides(&pool, &cache, &status, &overrides, 0, &mut modified);
 
-    thread::spawn(|| loop {
+    let sync_pool = pool.clone();
+    let sync_cache = cache.clone();
+    let sync_status = status.clone();
+    let notify = notifier(&pool);
+    thread::spawn(move || loop {
+        let (pool, cache, status) = (&sync_pool, &sync_cache, &sync_status);
         // Full check once per hour
-        update_packages(Check::FromStart);
-        update_outcast_packages();
+        let since = last_package_id(pool);
+        let inserted = update_packages(pool, cache, status, Check::FromStart)
+            + update_outcast_packages(pool, cache, status);
+        apply_overrides(pool, cache, status, &overrides, inserted, &mut modified);
+        if inserted > 0 {
+            notify_worker(&notify, since);
+        }
         for _ in 0..59 {
-            update_packages(Check::SinceLast);
+            let since = last_package_id(pool);
+            let inserted = update_packages(pool, cache, status, Check::SinceLast);
+            apply_overrides(pool, cache, status, &overrides, inserted, &mut modified);
+            if inserted > 0 {
+                notify_worker(&notify, since);
+            }
             thread::sleep(Duration::from_secs(60));
         }
     });
 
-    let get_rss = rss_packages(None, &Release::Any);
-    let get_rss_last = rss_packages(Some("last"), &Release::Last);
-    let get_rss_first = rss_packages(Some("first"), &Release::First);
-    let get_rss_major = rss_packages(Some("major"), &Release::Major);
-    let get_rss_minor = rss_packages(Some("minor"), &Release::Minor);
-    let get_rss_patch = rss_packages(Some("patch"), &Release::Patch);
+    let get_rss = rss_packages(&pool, &cache, None, &Release::Any);
+    let get_rss_last = rss_packages(&pool, &cache, Some("last"), &Release::Last);
+    let get_rss_first = rss_packages(&pool, &cache, Some("first"), &Release::First);
+    let get_rss_major = rss_packages(&pool, &cache, Some("major"), &Release::Major);
+    let get_rss_minor = rss_packages(&pool, &cache, Some("minor"), &Release::Minor);
+    let get_rss_patch = rss_packages(&pool, &cache, Some("patch"), &Release::Patch);
+
+    let post_project = create_project(&pool);
+    let get_project_rss = rss_project(&pool, &cache);
+    let get_upgrades_rss = rss_upgrades(&pool, &cache);
+    let post_outdated = outdated_api(&pool);
+    let post_solve = solve_api(&pool);
+    let get_graph = graph_api(&pool);
+    let get_snapshot = snapshot_api(&pool);
+    let get_history_rss = rss_history(&pool, &cache);
+    let get_history = history_api(&pool);
+    let get_author_rss = rss_author(&pool, &cache);
+    let get_author = author_api(&pool);
+    let get_stats = stats_api(&pool, &cache);
+    let get_stale_rss = rss_stale(&pool, &cache);
+    let get_stale = stale_api(&pool, &cache);
+    let get_events = release_events(&pool);
+    let post_websub = websub_hub(&websub::verifier(&pool));
+    let admin_webhooks = webhooks_api(&pool, &admin_token);
+    let get_healthz = healthz(&pool);
+    let get_status = status_report(&pool, &status);
+    let get_metrics = prometheus_metrics(&pool);
 
     // we should set the date with the more recent pubDate
     let head_rss = warp::head().and(warp::path(".rss")).map(warp::reply);
 
+    let get_precompressed = static_precompressed(&www_root, None);
+    let get_static = warp::get2().and(warp::fs::dir(www_root.clone()));
+    let default_precompressed = static_precompressed(&www_root, Some("index.html"));
     let default = warp::any().and(warp::fs::file(format!("{}/index.html", www_root)));
-    let get_static = warp::get2().and(warp::fs::dir(www_root));
 
     let routes = get_rss
         .or(get_rss_last)
@@ -63,45 +302,230 @@ fn main() -> syslog::Result<()> {
         .or(get_rss_minor)
         .or(get_rss_patch)
         .or(head_rss)
+        .or(post_project)
+        .or(get_project_rss)
+        .or(get_upgrades_rss)
+        .or(post_outdated)
+        .or(post_solve)
+        .or(get_graph)
+        .or(get_snapshot)
+        .or(get_history_rss)
+        .or(get_history)
+        .or(get_author_rss)
+        .or(get_author)
+        .or(get_stats)
+        .or(get_stale_rss)
+        .or(get_stale)
+        .or(get_events)
+        .or(post_websub)
+        .or(admin_webhooks)
+        .or(get_healthz)
+        .or(get_status)
+        .or(get_metrics)
+        .or(get_precompressed)
         .or(get_static)
+        .or(default_precompressed)
         .or(default);
 
     warp::serve(routes).run(([127, 0, 0, 1], 4242));
-    Ok(())
 }
 
-pub fn update_packages(check: Check) {
-    let conn = db::connect();
+/// Returns the number of new package releases
+pub fn update_packages(pool: &db::Pool, cache: &Cache, status: &Status, check: Check) -> usize {
+    let conn = pool.get().expect("Can't get database connection");
+    let start = Instant::now();
     let pkgs_count = db::count_packages(&conn, 19);
-    let save = |pkg: &NewPackage| db::save_package(&conn, pkg);
+    let inserted = Cell::new(0);
+    let save = |pkg: &NewPackage| {
+        if db::save_package(&conn, pkg) {
+            inserted.set(inserted.get() + 1);
+            cache.invalidate();
+        }
+    };
 
     if pkgs_count == 0 {
         log::info!("Checking all packages");
-        elm::packages::map(save);
+        status.record("packages", elm::packages::map(save));
     } else {
-        let start: i64 = match check {
+        let since: i64 = match check {
             Check::FromStart => 0,
             // Take a little margin in case some packages have been
             // removed on the official website.
             Check::SinceLast => std::cmp::max(pkgs_count - 16, 0),
         };
 
-        log::info!("Checking packages since {}", start);
-        elm::packages::map_since(save, start, &conn);
+        log::info!("Checking packages since {}", since);
+        let result = elm::packages::map_since(save, since, &conn);
+        // Stale packages depend on removed releases
+        if result == Ok(true) {
+            cache.invalidate();
+        }
+        status.record("packages", result.map(|_| ()));
+    }
+    metrics::sync_duration("packages", start);
+    inserted.get()
+}
+
+/// Apply the admin corrections file, if any, when releases were inserted or
+/// the file was edited since its `modified` time, as applying the same file
+/// again changes nothing.
+pub fn apply_overrides(
+    pool: &db::Pool,
+    cache: &Cache,
+    status: &Status,
+    path: &Option<String>,
+    inserted: usize,
+    modified: &mut Option<SystemTime>,
+) {
+    if let Some(path) = path {
+        let last_modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if inserted == 0 && last_modified.is_some() && last_modified == *modified {
+            return;
+        }
+        *modified = last_modified;
+        let conn = pool.get().expect("Can't get database connection");
+        let start = Instant::now();
+        status.record("overrides", overrides::apply_file(&conn, path));
+        metrics::sync_duration("overrides", start);
+        cache.invalidate();
+    }
+}
+
+/// Seed the database from a local mirror of the packages website,
+/// for air-gapped environments.
+pub fn import_snapshot(pool: &db::Pool, dir: &Path) {
+    let conn = pool.get().expect("Can't get database connection");
+    let save = |pkg: &NewPackage| {
+        db::save_package(&conn, pkg);
+    };
+
+    log::info!("Importing packages from {}", dir.display());
+    if let Err(err) = elm::snapshot::map(save, dir, &conn) {
+        log::error!("can't import {}: {}", dir.display(), err);
+        eprintln!("can't import {}: {}", dir.display(), err);
+        process::exit(1);
+    }
+}
+
+/// Import database rows from a JSON Lines file, or stdin with "-".
+pub fn import_rows(pool: &db::Pool, path: &str) {
+    let conn = pool.get().expect("Can't get database connection");
+    let result = if path == "-" {
+        db::jsonl::import(&conn, io::stdin().lock())
+    } else {
+        File::open(path)
+            .map_err(|err| format!("{}: {}", path, err))
+            .and_then(|file| db::jsonl::import(&conn, BufReader::new(file)))
+    };
+
+    match result {
+        Ok((imported, duplicates)) => log::info!(
+            "Imported {} rows from {}, {} duplicates ignored",
+            imported,
+            path,
+            duplicates
+        ),
+        Err(err) => {
+            log::error!("can't import {}: {}", path, err);
+            eprintln!("can't import {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Export database rows to a JSON Lines file, or stdout with "-".
+pub fn export_rows(pool: &db::Pool, path: &str) {
+    let conn = pool.get().expect("Can't get database connection");
+    let result = if path == "-" {
+        db::jsonl::export(&conn, io::stdout().lock())
+    } else {
+        File::create(path)
+            .map_err(|err| format!("{}: {}", path, err))
+            .and_then(|file| db::jsonl::export(&conn, BufWriter::new(file)))
+    };
+
+    match result {
+        Ok(count) => log::info!("Exported {} rows to {}", count, path),
+        Err(err) => {
+            log::error!("can't export {}: {}", path, err);
+            eprintln!("can't export {}: {}", path, err);
+            process::exit(1);
+        }
     }
 }
 
 /// 0.18 packages published after 0.19.0 release and some older
 /// ones are ignored by the packages API released with 0.19.0.
-pub fn update_outcast_packages() {
-    let conn = db::connect();
-    let save = |pkg: &NewPackage| db::save_package(&conn, pkg);
+/// Returns the number of new package releases
+pub fn update_outcast_packages(pool: &db::Pool, cache: &Cache, status: &Status) -> usize {
+    let conn = pool.get().expect("Can't get database connection");
+    let inserted = Cell::new(0);
+    let save = |pkg: &NewPackage| {
+        if db::save_package(&conn, pkg) {
+            inserted.set(inserted.get() + 1);
+            cache.invalidate();
+        }
+    };
 
     log::info!("Checking old format packages");
-    elm::old_format_packages::map(save, &conn);
+    let start = Instant::now();
+    status.record(
+        "old_format_packages",
+        elm::old_format_packages::map(save, &conn),
+    );
+    metrics::sync_duration("old_format_packages", start);
+    inserted.get()
+}
+
+fn last_package_id(pool: &db::Pool) -> i32 {
+    let conn = pool.get().expect("Can't get database connection");
+    db::last_package_id(&conn)
+}
+
+/// Hand new releases over to the notifier, synchronization going on when
+/// the worker is gone
+fn notify_worker(notify: &mpsc::Sender<i32>, since: i32) {
+    if let Err(err) = notify.send(since) {
+        log::error!(
+            "Can't notify subscribers of releases after {}: {}",
+            since,
+            err
+        );
+    }
+}
+
+/// Start the worker delivering new releases to WebSub subscribers and
+/// webhooks, so that slow callbacks don't delay synchronization. It receives
+/// the id of the last package before new releases.
+fn notifier(pool: &db::Pool) -> mpsc::Sender<i32> {
+    let (sender, receiver) = mpsc::channel();
+    let pool = pool.clone();
+    thread::spawn(move || {
+        while let Ok(since) = receiver.recv() {
+            // Releases inserted while delivering previous ones are delivered at once
+            let since = receiver.try_iter().fold(since, i32::min);
+            // A failed delivery must not stop the deliveries of next releases
+            let delivered =
+                panic::catch_unwind(AssertUnwindSafe(|| notify_subscribers(&pool, since)));
+            if delivered.is_err() {
+                log::error!("Can't notify subscribers of releases after {}", since);
+            }
+        }
+    });
+    sender
+}
+
+/// Push feeds and releases inserted after the package with id `since`
+/// to WebSub subscribers and webhooks
+pub fn notify_subscribers(pool: &db::Pool, since: i32) {
+    let conn = pool.get().expect("Can't get database connection");
+    websub::publish(&conn, since);
+    webhooks::deliver(&conn, since);
 }
 
 fn rss_packages(
+    pool: &db::Pool,
+    cache: &Cache,
     path: Option<&'static str>,
     release: &'static Release,
 ) -> BoxedFilter<(impl Reply,)> {
@@ -112,8 +536,739 @@ fn rss_packages(
         })
         .and(warp::path(".rss"))
         .and(warp::header("user-agent"))
+        .and(warp::header::optional("accept-encoding"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_pool(pool))
+        .and(with_cache(cache))
+        .map(
+            move |user_agent: String,
+                  accept_encoding,
+                  query: HashMap<String, String>,
+                  pool: db::Pool,
+                  cache: Cache| {
+                let route = release.route();
+                let _timer = metrics::request(route, "rss");
+                if let Err(err) = query::validate(&query) {
+                    return warp::reply::with_status(err, StatusCode::BAD_REQUEST).into_response();
+                }
+                let key = cache::Key::new(route, &query, &user_agent);
+                let rss = cache.get_or_render(key, || {
+                    let conn = pool.get().expect("Can't get database connection");
+                    let self_url = websub::topic_url(route, &query);
+                    rss::all(&conn, user_agent, query, release, &self_url)
+                });
+                compress::reply(&accept_encoding, "application/xml", &rss).into_response()
+            },
+        )
+        .boxed()
+}
+
+/// Save an uploaded elm.json and return the id of its dependencies feed
+fn create_project(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::post2()
+        .and(warp::path("p"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(256 * 1024))
+        .and(warp::body::concat())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(
+            |body: warp::body::FullBody, accept_encoding, pool: db::Pool| {
+                let _timer = metrics::request("/p", "json");
+                let conn = pool.get().expect("Can't get database connection");
+                let created = std::str::from_utf8(body.bytes())
+                    .map_err(|err| err.to_string())
+                    .and_then(|body| projects::create(&conn, body));
+                match created {
+                    Ok(project) => json_reply(
+                        &accept_encoding,
+                        &serde_json::json!({
+                            "id": project.id,
+                            "feed": projects::feed_url(&project.id),
+                            "upgrades": if projects::is_application(&project) {
+                                Some(projects::upgrades_url(&project.id))
+                            } else {
+                                None
+                            },
+                            "packages": projects::packages(&conn, &project),
+                        }),
+                        StatusCode::CREATED,
+                    ),
+                    Err(err) => json_error(&err, StatusCode::BAD_REQUEST),
+                }
+            },
+        )
+        .boxed()
+}
+
+/// Releases of the direct and indirect dependencies of an uploaded elm.json
+fn rss_project(pool: &db::Pool, cache: &Cache) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("p"))
+        .and(warp::path::param::<String>())
+        .and(warp::path(".rss"))
+        .and(warp::path::end())
+        .and(warp::header("user-agent"))
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .and(with_cache(cache))
+        .map(
+            |id: String, user_agent: String, accept_encoding, pool: db::Pool, cache: Cache| {
+                let _timer = metrics::request("/p/{id}/.rss", "rss");
+                let conn = pool.get().expect("Can't get database connection");
+                let project = match db::projects::get(&conn, &id) {
+                    Some(project) => project,
+                    None => {
+                        return warp::reply::with_status("unknown project", StatusCode::NOT_FOUND)
+                            .into_response()
+                    }
+                };
+                let route = format!("/p/{}/.rss", id);
+                let key = cache::Key::new(&route, &HashMap::new(), &user_agent);
+                let rss = cache.get_or_render(key, || {
+                    let filter = projects::filter(&projects::packages(&conn, &project));
+                    let self_url = projects::feed_url(&id);
+                    rss::project(&conn, user_agent, &id, filter, &Release::Any, &self_url)
+                });
+                compress::reply(&accept_encoding, "application/xml", &rss).into_response()
+            },
+        )
+        .boxed()
+}
+
+/// Releases newer than the versions pinned by an uploaded application
+fn rss_upgrades(pool: &db::Pool, cache: &Cache) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("p"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("upgrades"))
+        .and(warp::path(".rss"))
+        .and(warp::path::end())
+        .and(warp::header("user-agent"))
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .and(with_cache(cache))
+        .map(
+            |id: String, user_agent: String, accept_encoding, pool: db::Pool, cache: Cache| {
+                let _timer = metrics::request("/p/{id}/upgrades/.rss", "rss");
+                let conn = pool.get().expect("Can't get database connection");
+                let project = match db::projects::get(&conn, &id) {
+                    Some(project) if projects::is_application(&project) => project,
+                    _ => {
+                        return warp::reply::with_status(
+                            "unknown application",
+                            StatusCode::NOT_FOUND,
+                        )
+                        .into_response()
+                    }
+                };
+                let route = format!("/p/{}/upgrades/.rss", id);
+                let key = cache::Key::new(&route, &HashMap::new(), &user_agent);
+                let rss = cache.get_or_render(key, || {
+                    let upgrades = projects::upgrades(&conn, &project).unwrap_or_default();
+                    let self_url = projects::upgrades_url(&id);
+                    rss::upgrades(user_agent, &id, &upgrades, &self_url)
+                });
+                compress::reply(&accept_encoding, "application/xml", &rss).into_response()
+            },
+        )
+        .boxed()
+}
+
+/// Every release of a package, annotated with changes since the previous one
+fn rss_history(pool: &db::Pool, cache: &Cache) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("packages"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::param::<String>())
+        .and(warp::path(".rss"))
+        .and(warp::path::end())
+        .and(warp::header("user-agent"))
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .and(with_cache(cache))
+        .map(
+            |author: String,
+             name: String,
+             user_agent: String,
+             accept_encoding,
+             pool: db::Pool,
+             cache: Cache| {
+                let _timer = metrics::request("/packages/{author}/{name}/.rss", "rss");
+                let repo = format!("{}/{}", author, name);
+                let route = format!("/packages/{}/.rss", repo);
+                let key = cache::Key::new(&route, &HashMap::new(), &user_agent);
+                // Unknown packages are cached as empty feeds
+                let rss = cache.get_or_render(key, || {
+                    let conn = pool.get().expect("Can't get database connection");
+                    let releases = db::package_releases(&conn, &repo);
+                    if releases.is_empty() {
+                        return String::new();
+                    }
+                    let self_url = websub::topic_url(&route, &HashMap::new());
+                    rss::history(&conn, user_agent, &repo, &releases, &self_url)
+                });
+                if rss.is_empty() {
+                    return warp::reply::with_status("unknown package", StatusCode::NOT_FOUND)
+                        .into_response();
+                }
+                compress::reply(&accept_encoding, "application/xml", &rss).into_response()
+            },
+        )
+        .boxed()
+}
+
+/// Every release of a package with durations between releases, supported
+/// elm versions and license changes
+fn history_api(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("packages"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(
+            |author: String, name: String, accept_encoding, pool: db::Pool| {
+                let _timer = metrics::request("/api/v1/packages/{author}/{name}", "json");
+                let conn = pool.get().expect("Can't get database connection");
+                let repo = format!("{}/{}", author, name);
+                let releases = db::package_releases(&conn, &repo);
+                if releases.is_empty() {
+                    return json_error("unknown package", StatusCode::NOT_FOUND);
+                }
+                json_reply(
+                    &accept_encoding,
+                    &history::history(&repo, &releases),
+                    StatusCode::OK,
+                )
+            },
+        )
+        .boxed()
+}
+
+/// Releases of all packages of an author
+fn rss_author(pool: &db::Pool, cache: &Cache) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("authors"))
+        .and(warp::path::param::<String>())
+        .and(warp::path(".rss"))
+        .and(warp::path::end())
+        .and(warp::header("user-agent"))
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .and(with_cache(cache))
+        .map(
+            |author: String, user_agent: String, accept_encoding, pool: db::Pool, cache: Cache| {
+                let _timer = metrics::request("/authors/{author}/.rss", "rss");
+                let conn = pool.get().expect("Can't get database connection");
+                if db::author_packages(&conn, &author).is_empty() {
+                    return warp::reply::with_status("unknown author", StatusCode::NOT_FOUND)
+                        .into_response();
+                }
+                let route = format!("/authors/{}/.rss", author);
+                let key = cache::Key::new(&route, &HashMap::new(), &user_agent);
+                let rss = cache.get_or_render(key, || {
+                    let query: HashMap<String, String> =
+                        [(author, "*".to_string())].into_iter().collect();
+                    let self_url = websub::topic_url(&route, &HashMap::new());
+                    rss::all(&conn, user_agent, query, &Release::Any, &self_url)
+                });
+                compress::reply(&accept_encoding, "application/xml", &rss).into_response()
+            },
+        )
+        .boxed()
+}
+
+/// Packages of an author with their latest release, first publication and
+/// number of releases, and the releases of the author per year
+fn author_api(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("authors"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(|author: String, accept_encoding, pool: db::Pool| {
+            let _timer = metrics::request("/api/v1/authors/{author}", "json");
+            let conn = pool.get().expect("Can't get database connection");
+            let releases = authors::releases(&conn, &author);
+            match authors::profile(&author, &releases) {
+                Some(profile) => json_reply(&accept_encoding, &profile, StatusCode::OK),
+                None => json_error("unknown author", StatusCode::NOT_FOUND),
+            }
+        })
+        .boxed()
+}
+
+/// Statistics of all releases, cached until new releases are inserted
+fn stats_api(pool: &db::Pool, cache: &Cache) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .and(with_cache(cache))
+        .map(|accept_encoding, pool: db::Pool, cache: Cache| {
+            let _timer = metrics::request("/api/v1/stats", "json");
+            let key = cache::Key::new("/api/v1/stats", &HashMap::new(), "");
+            let json = cache.get_or_render(key, || {
+                let conn = pool.get().expect("Can't get database connection");
+                let stats = stats::stats(&db::visible_releases(&conn));
+                serde_json::to_string(&stats).expect("Can't serialize stats")
+            });
+            compress::reply(&accept_encoding, "application/json", &json)
+        })
+        .boxed()
+}
+
+/// Latest releases of inactive packages, not supporting the current elm
+/// version or depending on removed packages
+fn rss_stale(pool: &db::Pool, cache: &Cache) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("stale"))
+        .and(warp::path(".rss"))
+        .and(warp::path::end())
+        .and(warp::header("user-agent"))
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .and(with_cache(cache))
+        .map(
+            |user_agent: String, accept_encoding, pool: db::Pool, cache: Cache| {
+                let _timer = metrics::request("/stale/.rss", "rss");
+                let key = cache::Key::new("/stale/.rss", &HashMap::new(), &user_agent);
+                let rss = cache.get_or_render(key, || {
+                    let conn = pool.get().expect("Can't get database connection");
+                    let self_url = websub::topic_url("/stale/.rss", &HashMap::new());
+                    rss::stale(&conn, user_agent, &self_url)
+                });
+                compress::reply(&accept_encoding, "application/xml", &rss)
+            },
+        )
+        .boxed()
+}
+
+/// Stale packages among all packages or those matching a feed filter, with
+/// the reasons why they are stale
+fn stale_api(pool: &db::Pool, cache: &Cache) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("stale"))
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .and(with_cache(cache))
+        .map(
+            |query: HashMap<String, String>, accept_encoding, pool: db::Pool, cache: Cache| {
+                let _timer = metrics::request("/api/v1/stale", "json");
+                if let Err(err) = query::validate(&query) {
+                    return json_error(&err, StatusCode::BAD_REQUEST);
+                }
+                let key = cache::Key::new("/api/v1/stale", &query, "");
+                let json = cache.get_or_render(key, || {
+                    let conn = pool.get().expect("Can't get database connection");
+                    let latest = db::latest_releases(&conn, query, None);
+                    let removed = stale::removed_dependencies(&conn, &latest);
+                    let stale = stale::list(&latest, &removed, &stale::criteria());
+                    serde_json::to_string(&stale).expect("Can't serialize stale packages")
+                });
+                compress::reply(&accept_encoding, "application/json", &json)
+            },
+        )
+        .boxed()
+}
+
+/// Outdated dependencies of a POSTed elm.json
+fn outdated_api(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::post2()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("outdated"))
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::body::content_length_limit(256 * 1024))
+        .and(warp::body::concat())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(
+            |query, body: warp::body::FullBody, accept_encoding, pool: db::Pool| {
+                let _timer = metrics::request("/api/v1/outdated", "json");
+                let request = std::str::from_utf8(body.bytes())
+                    .map_err(|err| err.to_string())
+                    .and_then(elm::project::parse)
+                    .and_then(|elm_json| Ok((elm_json, outdated::Threshold::from_query(&query)?)));
+                match request {
+                    Ok((elm_json, threshold)) => {
+                        let conn = pool.get().expect("Can't get database connection");
+                        let report = outdated::report(&conn, &elm_json, &threshold);
+                        json_reply(&accept_encoding, &report, StatusCode::OK)
+                    }
+                    Err(err) => json_error(&err, StatusCode::BAD_REQUEST),
+                }
+            },
+        )
+        .boxed()
+}
+
+/// Newest mutually compatible dependencies of a POSTed application elm.json
+fn solve_api(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::post2()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("solve"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(256 * 1024))
+        .and(warp::body::concat())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(
+            |body: warp::body::FullBody, accept_encoding, pool: db::Pool| {
+                let _timer = metrics::request("/api/v1/solve", "json");
+                let conn = pool.get().expect("Can't get database connection");
+                let report = std::str::from_utf8(body.bytes())
+                    .map_err(|err| err.to_string())
+                    .and_then(elm::project::parse)
+                    .and_then(|elm_json| elm::solver::report(&conn, &elm_json));
+                match report {
+                    Ok(report) => json_reply(&accept_encoding, &report, StatusCode::OK),
+                    Err(err) => json_error(&err, StatusCode::BAD_REQUEST),
+                }
+            },
+        )
+        .boxed()
+}
+
+/// Dependency graph of packages matching a feed filter, as JSON or with
+/// `_format=dot`, optionally `_at` a date
+fn graph_api(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("graph"))
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(
+            |mut query: HashMap<String, String>, accept_encoding, pool: db::Pool| {
+                let format = query
+                    .remove("_format")
+                    .map(|format| format.parse::<graph::Format>())
+                    .unwrap_or(Ok(graph::Format::Json));
+                let at = query
+                    .remove("_at")
+                    .map(|date| query::timestamp(&date))
+                    .transpose();
+                let (format, at) = match (format, at) {
+                    (Ok(format), Ok(at)) => (format, at),
+                    (Err(err), _) | (_, Err(err)) => {
+                        return json_error(&err, StatusCode::BAD_REQUEST)
+                    }
+                };
+
+                let conn = pool.get().expect("Can't get database connection");
+                let graph = graph::build(&conn, query, at);
+                match format {
+                    graph::Format::Dot => {
+                        let _timer = metrics::request("/api/v1/graph", "dot");
+                        compress::reply(&accept_encoding, "text/vnd.graphviz", &graph::dot(&graph))
+                    }
+                    graph::Format::Json => {
+                        let _timer = metrics::request("/api/v1/graph", "json");
+                        json_reply(&accept_encoding, &graph, StatusCode::OK)
+                    }
+                }
+            },
+        )
+        .boxed()
+}
+
+/// Latest release of each package published by `_at` a date, or now, of
+/// all packages or those matching a feed filter
+fn snapshot_api(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("snapshot"))
+        .and(warp::path::end())
         .and(warp::query::<HashMap<String, String>>())
-        .map(move |user_agent, query| rss::all(user_agent, query, release))
-        .with(warp::reply::with::header("content-type", "application/xml"))
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(
+            |mut query: HashMap<String, String>, accept_encoding, pool: db::Pool| {
+                let _timer = metrics::request("/api/v1/snapshot", "json");
+                let at = match query.remove("_at").map(|date| query::timestamp(&date)) {
+                    Some(Ok(at)) => at,
+                    Some(Err(err)) => return json_error(&err, StatusCode::BAD_REQUEST),
+                    None => Utc::now().timestamp(),
+                };
+
+                let conn = pool.get().expect("Can't get database connection");
+                let releases = db::latest_releases(&conn, query, Some(at));
+                let packages: Vec<api::PackageRelease> = releases.iter().map(Into::into).collect();
+                let date = match Utc.timestamp_opt(at, 0).single() {
+                    Some(date) => date.to_rfc3339(),
+                    None => return json_error("invalid date", StatusCode::BAD_REQUEST),
+                };
+                json_reply(
+                    &accept_encoding,
+                    &serde_json::json!({ "at": date, "packages": packages }),
+                    StatusCode::OK,
+                )
+            },
+        )
         .boxed()
 }
+
+/// Server-sent events of new releases matching a feed filter, with an
+/// optional `_release` kind
+fn release_events(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::path("events")
+        .and(warp::path::end())
+        .and(warp::sse())
+        .and(warp::header::optional::<String>("last-event-id"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_pool(pool))
+        .map(
+            |sse: warp::sse::Sse,
+             last_event_id: Option<String>,
+             mut query: HashMap<String, String>,
+             pool: db::Pool| {
+                let _timer = metrics::request("/events", "sse");
+                let release = match query.remove("_release").map(|kind| kind.parse::<Release>()) {
+                    Some(Ok(release)) => release,
+                    Some(Err(err)) => {
+                        return json_error(&err, StatusCode::BAD_REQUEST).into_response()
+                    }
+                    None => Release::Any,
+                };
+                if let Err(err) = query::validate(&query) {
+                    return json_error(&err, StatusCode::BAD_REQUEST).into_response();
+                }
+                let since = match last_event_id.map(|id| id.parse::<i32>()) {
+                    Some(Ok(id)) => id,
+                    Some(Err(_)) => {
+                        return json_error("invalid Last-Event-ID", StatusCode::BAD_REQUEST)
+                            .into_response()
+                    }
+                    None => last_package_id(&pool),
+                };
+                let events = events::releases(pool, query, release, since);
+                sse.reply(warp::sse::keep_alive().stream(events))
+                    .into_response()
+            },
+        )
+        .boxed()
+}
+
+/// WebSub hub accepting subscriptions to feeds of this instance
+fn websub_hub(verifier: &websub::Verifier) -> BoxedFilter<(impl Reply,)> {
+    let verifier = verifier.clone();
+    warp::post2()
+        .and(warp::path("websub"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::form::<websub::Request>())
+        .map(move |request| {
+            let _timer = metrics::request("/websub", "text");
+            match websub::request(&verifier, request) {
+                Ok(()) => warp::reply::with_status(String::new(), StatusCode::ACCEPTED),
+                Err(websub::Refusal::Invalid(err)) => {
+                    warp::reply::with_status(err, StatusCode::BAD_REQUEST)
+                }
+                Err(websub::Refusal::Busy) => warp::reply::with_status(
+                    "too many pending verifications".to_string(),
+                    StatusCode::SERVICE_UNAVAILABLE,
+                ),
+            }
+        })
+        .boxed()
+}
+
+/// Admin API listing, registering and deleting webhooks
+fn webhooks_api(pool: &db::Pool, admin_token: &Option<String>) -> BoxedFilter<(impl Reply,)> {
+    let path = || {
+        warp::path("api")
+            .and(warp::path("v1"))
+            .and(warp::path("webhooks"))
+    };
+
+    let list = warp::get2()
+        .and(path())
+        .and(warp::path::end())
+        .and(with_admin(admin_token))
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(|admin: bool, accept_encoding, pool: db::Pool| {
+            let _timer = metrics::request("/api/v1/webhooks", "json");
+            if !admin {
+                return json_error("unauthorized", StatusCode::UNAUTHORIZED);
+            }
+            let conn = pool.get().expect("Can't get database connection");
+            json_reply(&accept_encoding, &db::webhooks::all(&conn), StatusCode::OK)
+        });
+
+    let create = warp::post2()
+        .and(path())
+        .and(warp::path::end())
+        .and(with_admin(admin_token))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json::<webhooks::Registration>())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(
+            |admin: bool, registration, accept_encoding, pool: db::Pool| {
+                let _timer = metrics::request("/api/v1/webhooks", "json");
+                if !admin {
+                    return json_error("unauthorized", StatusCode::UNAUTHORIZED);
+                }
+                let conn = pool.get().expect("Can't get database connection");
+                match webhooks::register(&conn, registration) {
+                    Ok(webhook) => json_reply(&accept_encoding, &webhook, StatusCode::CREATED),
+                    Err(err) => json_error(&err, StatusCode::BAD_REQUEST),
+                }
+            },
+        );
+
+    let delete = warp::delete2()
+        .and(path())
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(with_admin(admin_token))
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(|webhook_id, admin: bool, accept_encoding, pool: db::Pool| {
+            let _timer = metrics::request("/api/v1/webhooks/{id}", "json");
+            if !admin {
+                return json_error("unauthorized", StatusCode::UNAUTHORIZED);
+            }
+            let conn = pool.get().expect("Can't get database connection");
+            match db::webhooks::delete(&conn, webhook_id) {
+                0 => json_error("unknown webhook", StatusCode::NOT_FOUND),
+                _ => json_reply(&accept_encoding, &webhook_id, StatusCode::OK),
+            }
+        });
+
+    list.or(create).or(delete).boxed()
+}
+
+/// Process is up and database is reachable
+fn healthz(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .and(with_pool(pool))
+        .map(|pool: db::Pool| {
+            let _timer = metrics::request("/healthz", "text");
+            let reachable = pool.get().map(|conn| db::ping(&conn)).unwrap_or(false);
+            if reachable {
+                warp::reply::with_status("ok", StatusCode::OK)
+            } else {
+                warp::reply::with_status("database unreachable", StatusCode::SERVICE_UNAVAILABLE)
+            }
+        })
+        .boxed()
+}
+
+/// Synchronization status and dataset freshness
+fn status_report(pool: &db::Pool, status: &Status) -> BoxedFilter<(impl Reply,)> {
+    let status = status.clone();
+    warp::get2()
+        .and(warp::path("status"))
+        .and(warp::path::end())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(move |accept_encoding, pool: db::Pool| {
+            let _timer = metrics::request("/status", "json");
+            let conn = pool.get().expect("Can't get database connection");
+            let report = status.report(
+                db::count_releases_by_format(&conn).into_iter().collect(),
+                db::newest_timestamp(&conn),
+            );
+            let json = serde_json::to_string(&report).expect("Can't serialize status");
+            compress::reply(&accept_encoding, "application/json", &json)
+        })
+        .boxed()
+}
+
+/// Prometheus metrics
+fn prometheus_metrics(pool: &db::Pool) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(warp::header::optional("accept-encoding"))
+        .and(with_pool(pool))
+        .map(|accept_encoding, pool: db::Pool| {
+            let releases = pool
+                .get()
+                .map(|conn| db::count_releases_by_format(&conn))
+                .unwrap_or_default();
+            let metrics = metrics::render(&releases);
+            compress::reply(&accept_encoding, "text/plain; version=0.0.4", &metrics)
+        })
+        .boxed()
+}
+
+/// Serve compressed static files, or a given file for any path.
+fn static_precompressed(www_root: &str, file: Option<&'static str>) -> BoxedFilter<(impl Reply,)> {
+    let www_root = www_root.to_string();
+    warp::get2()
+        .and(warp::path::tail())
+        .and(warp::header::optional("accept-encoding"))
+        .and_then(move |tail: warp::path::Tail, accept_encoding| {
+            let path = file.unwrap_or(tail.as_str());
+            compress::static_file(&www_root, path, &accept_encoding)
+                .ok_or_else(warp::reject::not_found)
+        })
+        .boxed()
+}
+
+fn json_reply<T: serde::Serialize>(
+    accept_encoding: &Option<String>,
+    value: &T,
+    status: StatusCode,
+) -> Response<Vec<u8>> {
+    let json = serde_json::to_string(value).expect("Can't serialize response");
+    let mut response = compress::reply(accept_encoding, "application/json", &json);
+    *response.status_mut() = status;
+    response
+}
+
+/// Error messages are too short to be worth compressing
+fn json_error(message: &str, status: StatusCode) -> Response<Vec<u8>> {
+    json_reply(&None, &serde_json::json!({ "error": message }), status)
+}
+
+/// Whether a request carries the admin token as a bearer token. The admin
+/// API is disabled when ADMIN_TOKEN is not set.
+fn with_admin(admin_token: &Option<String>) -> BoxedFilter<(bool,)> {
+    let admin_token = admin_token.clone();
+    warp::header::optional::<String>("authorization")
+        .map(
+            move |authorization: Option<String>| match (&admin_token, authorization) {
+                (Some(token), Some(authorization)) => {
+                    authorization.strip_prefix("Bearer ") == Some(token.as_str())
+                }
+                _ => false,
+            },
+        )
+        .boxed()
+}
+
+fn with_pool(pool: &db::Pool) -> BoxedFilter<(db::Pool,)> {
+    let pool = pool.clone();
+    warp::any().map(move || pool.clone()).boxed()
+}
+
+fn with_cache(cache: &Cache) -> BoxedFilter<(Cache,)> {
+    let cache = cache.clone();
+    warp::any().map(move || cache.clone()).boxed()
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Upper bounds in seconds of request latency histogram buckets
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Process-wide metrics, updated like log records from anywhere in the
+/// server and rendered in the Prometheus text format.
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    requests: BTreeMap::new(),
+    cache: BTreeMap::new(),
+    sync_durations: BTreeMap::new(),
+    upstream_errors: BTreeMap::new(),
+    ignored_packages: 0,
+});
+
+struct Metrics {
+    /// (route, format) -> latencies
+    requests: BTreeMap<(String, &'static str), Histogram>,
+    /// hit or miss -> count
+    cache: BTreeMap<&'static str, u64>,
+    /// source -> seconds of last synchronization
+    sync_durations: BTreeMap<&'static str, f64>,
+    /// upstream endpoint -> count
+    upstream_errors: BTreeMap<&'static str, u64>,
+    ignored_packages: u64,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS.iter()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Records the latency of a request when dropped
+pub struct RequestTimer {
+    route: String,
+    format: &'static str,
+    start: Instant,
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        log::debug!(
+            route = self.route.as_str(), format = self.format, duration = elapsed;
+            "Served {} in {:.3}s", self.route, elapsed
+        );
+        let mut metrics = METRICS.lock().unwrap();
+        metrics
+            .requests
+            .entry((std::mem::take(&mut self.route), self.format))
+            .or_default()
+            .observe(elapsed);
+    }
+}
+
+/// Start timing a request of a route returning a format, e.g. "rss" or "json"
+pub fn request(route: &str, format: &'static str) -> RequestTimer {
+    RequestTimer {
+        route: route.to_string(),
+        format,
+        start: Instant::now(),
+    }
+}
+
+pub fn cache(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    *METRICS.lock().unwrap().cache.entry(result).or_insert(0) += 1;
+}
+
+pub fn sync_duration(source: &'static str, start: Instant) {
+    let elapsed = start.elapsed().as_secs_f64();
+    METRICS
+        .lock()
+        .unwrap()
+        .sync_durations
+        .insert(source, elapsed);
+}
+
+/// Count an error of an upstream endpoint: "all-packages", "releases.json",
+/// "elm.json" or "old-format"
+pub fn upstream_error(endpoint: &'static str) {
+    *METRICS
+        .lock()
+        .unwrap()
+        .upstream_errors
+        .entry(endpoint)
+        .or_insert(0) += 1;
+}
+
+pub fn ignored_package() {
+    METRICS.lock().unwrap().ignored_packages += 1;
+}
+
+/// Render metrics, with the number of releases per format from the database
+pub fn render(releases: &[(i32, i64)]) -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "greenwood_http_requests_total",
+        "counter",
+        "HTTP requests per route and format.",
+    );
+    for ((route, format), histogram) in &metrics.requests {
+        let _ = writeln!(
+            out,
+            "greenwood_http_requests_total{{route=\"{}\",format=\"{}\"}} {}",
+            escape(route),
+            format,
+            histogram.count
+        );
+    }
+
+    header(
+        &mut out,
+        "greenwood_http_request_duration_seconds",
+        "histogram",
+        "HTTP request latencies per route and format.",
+    );
+    for ((route, format), histogram) in &metrics.requests {
+        let labels = format!("route=\"{}\",format=\"{}\"", escape(route), format);
+        for (count, bound) in histogram.buckets.iter().zip(BUCKETS.iter()) {
+            let _ = writeln!(
+                out,
+                "greenwood_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                labels, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "greenwood_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+            labels, histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "greenwood_http_request_duration_seconds_sum{{{}}} {}",
+            labels, histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "greenwood_http_request_duration_seconds_count{{{}}} {}",
+            labels, histogram.count
+        );
+    }
+
+    header(
+        &mut out,
+        "greenwood_cache_requests_total",
+        "counter",
+        "Feed cache lookups per result.",
+    );
+    for (result, count) in &metrics.cache {
+        let _ = writeln!(
+            out,
+            "greenwood_cache_requests_total{{result=\"{}\"}} {}",
+            result, count
+        );
+    }
+
+    header(
+        &mut out,
+        "greenwood_releases",
+        "gauge",
+        "Package releases per format.",
+    );
+    for (format, count) in releases {
+        let _ = writeln!(out, "greenwood_releases{{format=\"{}\"}} {}", format, count);
+    }
+
+    header(
+        &mut out,
+        "greenwood_sync_duration_seconds",
+        "gauge",
+        "Duration of the last synchronization per source.",
+    );
+    for (source, seconds) in &metrics.sync_durations {
+        let _ = writeln!(
+            out,
+            "greenwood_sync_duration_seconds{{source=\"{}\"}} {}",
+            source, seconds
+        );
+    }
+
+    header(
+        &mut out,
+        "greenwood_upstream_errors_total",
+        "counter",
+        "Errors of the packages website per endpoint.",
+    );
+    for (endpoint, count) in &metrics.upstream_errors {
+        let _ = writeln!(
+            out,
+            "greenwood_upstream_errors_total{{endpoint=\"{}\"}} {}",
+            endpoint, count
+        );
+    }
+
+    header(
+        &mut out,
+        "greenwood_ignored_packages_total",
+        "counter",
+        "Invalid package releases ignored.",
+    );
+    let _ = writeln!(
+        out,
+        "greenwood_ignored_packages_total {}",
+        metrics.ignored_packages
+    );
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,395 @@
+use crate::db;
+use crate::db::models::Package;
+use crate::elm::project::ElmJson;
+use crate::elm::version::{Constraint, Version};
+use diesel::sqlite::SqliteConnection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// How far a dependency is behind its latest release
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lag {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl FromStr for Lag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Lag, String> {
+        match s {
+            "patch" => Ok(Lag::Patch),
+            "minor" => Ok(Lag::Minor),
+            "major" => Ok(Lag::Major),
+            _ => Err(format!("unknown level {}", s)),
+        }
+    }
+}
+
+/// Dependencies lagging at least `level` are outdated, and more than `max`
+/// outdated dependencies exceed the threshold, any of them when only
+/// `level` is given.
+pub struct Threshold {
+    pub level: Option<Lag>,
+    pub max: Option<usize>,
+}
+
+impl Threshold {
+    /// Threshold of API requests, e.g. "?level=minor&max=0"
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Threshold, String> {
+        Ok(Threshold {
+            level: query.get("level").map(|level| level.parse()).transpose()?,
+            max: query
+                .get("max")
+                .map(|max| max.parse().map_err(|_| format!("invalid max {}", max)))
+                .transpose()?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub dependencies: Vec<Dependency>,
+    pub outdated: usize,
+    pub exceeded: bool,
+}
+
+#[derive(Serialize)]
+pub struct Dependency {
+    pub package: String,
+    /// Pinned version of applications, or constraint of packages
+    pub constraint: String,
+    /// Pinned version of applications, or newest release in the constraint
+    /// of packages
+    pub current: Option<Version>,
+    pub latest: Option<Version>,
+    pub lag: Option<Lag>,
+    /// Whether the latest release is in the declared constraint, releases of
+    /// the same major version being compatible with pinned versions
+    pub latest_in_constraint: bool,
+    /// Whether the latest release supports the declared elm version
+    pub latest_supports_elm: bool,
+    /// Newest release newer than the current one, in the constraint and
+    /// supporting the elm version
+    pub newest_compatible: Option<Version>,
+}
+
+/// Compare the dependencies of an elm.json with the latest releases
+pub fn report(conn: &SqliteConnection, elm_json: &ElmJson, threshold: &Threshold) -> Report {
+    let (constraints, elm) = match elm_json {
+        ElmJson::Application(app) => {
+            let pins = app
+                .dependencies
+                .direct
+                .iter()
+                .chain(app.dependencies.indirect.iter())
+                .map(|(pkg, pin)| (pkg.clone(), pin.clone()))
+                .collect::<BTreeMap<String, String>>();
+            let elm = app.elm_version.parse::<Version>().ok().map(|v| Constraint {
+                lower: v,
+                lower_inclusive: true,
+                upper: v,
+                upper_inclusive: true,
+            });
+            (pins, elm)
+        }
+        ElmJson::Package(pkg) => (pkg.dependencies.clone(), pkg.elm_version.parse().ok()),
+    };
+
+    let dependencies: Vec<Dependency> = constraints
+        .into_iter()
+        .map(|(package, constraint)| {
+            let releases = db::package_releases(conn, &package);
+            dependency(package, constraint, &releases, &elm)
+        })
+        .collect();
+
+    let level = threshold.level.unwrap_or(Lag::Patch);
+    let outdated = dependencies
+        .iter()
+        .filter(|dep| dep.lag.map(|lag| lag >= level).unwrap_or(false))
+        .count();
+    Report {
+        exceeded: threshold
+            .max
+            .or(threshold.level.map(|_| 0))
+            .map(|max| outdated > max)
+            .unwrap_or(false),
+        dependencies,
+        outdated,
+    }
+}
+
+fn dependency(
+    package: String,
+    constraint: String,
+    releases: &[Package],
+    elm: &Option<Constraint>,
+) -> Dependency {
+    // Pinned versions accept releases of the same major version
+    let range = match constraint.parse::<Version>() {
+        Ok(pin) => Some(Constraint {
+            lower: pin,
+            lower_inclusive: true,
+            upper: Version::new(pin.major + 1, 0, 0),
+            upper_inclusive: false,
+        }),
+        Err(_) => constraint.parse::<Constraint>().ok(),
+    };
+    let in_range = |v: &Version| range.map(|r| r.contains(v)).unwrap_or(false);
+    let supports_elm = |pkg: &Package| match (elm, pkg.elm_version.parse::<Constraint>()) {
+        (Some(elm), Ok(supported)) => elm.intersects(&supported),
+        (None, _) => true,
+        (Some(_), Err(_)) => false,
+    };
+    let version = |pkg: &Package| Version::new(pkg.major, pkg.minor, pkg.patch);
+
+    let current = match constraint.parse::<Version>() {
+        Ok(pin) => Some(pin),
+        Err(_) => releases.iter().map(version).filter(in_range).max(),
+    };
+    let latest = releases.iter().max_by_key(|pkg| version(pkg));
+    let newest_compatible = releases
+        .iter()
+        .filter(|pkg| supports_elm(pkg))
+        .map(version)
+        .filter(|v| in_range(v) && Some(*v) > current)
+        .max();
+    let lag = match (current, latest.map(version)) {
+        (Some(current), Some(latest)) if latest > current => {
+            Some(if latest.major > current.major {
+                Lag::Major
+            } else if latest.minor > current.minor {
+                Lag::Minor
+            } else {
+                Lag::Patch
+            })
+        }
+        (None, Some(_)) => Some(Lag::Major),
+        _ => None,
+    };
+
+    Dependency {
+        package,
+        constraint,
+        current,
+        latest: latest.map(version),
+        lag,
+        latest_in_constraint: latest.map(|pkg| in_range(&version(pkg))).unwrap_or(false),
+        latest_supports_elm: latest.map(supports_elm).unwrap_or(false),
+        newest_compatible,
+    }
+}
+
+pub const USAGE: &str =
+    "greenwood outdated [--level patch|minor|major] [--max N] [--json] [path/to/elm.json]";
+
+pub struct Options {
+    pub threshold: Threshold,
+    pub json: bool,
+    pub path: String,
+}
+
+impl Options {
+    /// Parse the arguments following "outdated"
+    pub fn parse(args: &[&str]) -> Result<Options, String> {
+        let mut options = Options {
+            threshold: Threshold {
+                level: None,
+                max: None,
+            },
+            json: false,
+            path: "elm.json".to_string(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match *arg {
+                "--level" => options.threshold.level = Some(value()?.parse()?),
+                "--max" => {
+                    let max = value()?;
+                    options.threshold.max =
+                        Some(max.parse().map_err(|_| format!("invalid --max {}", max))?)
+                }
+                "--json" => options.json = true,
+                path if !path.starts_with("--") => options.path = path.to_string(),
+                other => return Err(format!("unknown option {}", other)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Human readable report, one line per dependency
+pub fn text(report: &Report) -> String {
+    let show = |v: &Option<Version>| v.map(|v| v.to_string()).unwrap_or("-".to_string());
+    let mut lines: Vec<String> = report
+        .dependencies
+        .iter()
+        .filter(|dep| dep.lag.is_some())
+        .map(|dep| {
+            let mut notes = Vec::new();
+            if !dep.latest_in_constraint {
+                notes.push("outside constraint".to_string());
+            }
+            if !dep.latest_supports_elm {
+                notes.push("unsupported elm version".to_string());
+            }
+            if let Some(version) = dep.newest_compatible {
+                notes.push(format!("{} compatible", version));
+            }
+            format!(
+                "{:40} {:>10} -> {:10} {:6} {}",
+                dep.package,
+                show(&dep.current),
+                show(&dep.latest),
+                dep.lag.map(lag_name).unwrap_or(""),
+                notes.join(", ")
+            )
+            .trim_end()
+            .to_string()
+        })
+        .collect();
+    lines.push(format!(
+        "{} outdated dependencies{}",
+        report.outdated,
+        if report.exceeded {
+            ", threshold exceeded"
+        } else {
+            ""
+        }
+    ));
+    lines.join("\n")
+}
+
+fn lag_name(lag: Lag) -> &'static str {
+    match lag {
+        Lag::Patch => "patch",
+        Lag::Minor => "minor",
+        Lag::Major => "major",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elm::{project, snapshot};
+
+    fn application(elm_version: &str, direct: &str) -> ElmJson {
+        project::parse(&format!(
+            r#"{{ "type": "application", "elm-version": "{}",
+                  "dependencies": {{ "direct": {}, "indirect": {{}} }} }}"#,
+            elm_version, direct
+        ))
+        .unwrap()
+    }
+
+    fn threshold(level: Option<Lag>, max: Option<usize>) -> Threshold {
+        Threshold { level, max }
+    }
+
+    #[test]
+    fn classifies_pinned_versions() {
+        let conn = snapshot::fixtures();
+        let elm_json = application(
+            "0.19.1",
+            r#"{ "elm/core": "1.0.0", "elm/json": "1.0.0", "elm/http": "1.0.0" }"#,
+        );
+        let report = report(&conn, &elm_json, &threshold(None, None));
+
+        let lags: Vec<(&str, Option<Lag>)> = report
+            .dependencies
+            .iter()
+            .map(|dep| (dep.package.as_str(), dep.lag))
+            .collect();
+        assert_eq!(
+            lags,
+            vec![
+                ("elm/core", Some(Lag::Patch)),
+                ("elm/http", Some(Lag::Major)),
+                ("elm/json", Some(Lag::Minor)),
+            ]
+        );
+        let http = &report.dependencies[1];
+        assert!(!http.latest_in_constraint);
+        assert!(http.latest_supports_elm);
+        assert_eq!(http.newest_compatible, None);
+        let json = &report.dependencies[2];
+        assert!(json.latest_in_constraint);
+        assert_eq!(json.newest_compatible, Some(Version::new(1, 1, 3)));
+        assert_eq!(report.outdated, 3);
+        assert!(!report.exceeded);
+    }
+
+    #[test]
+    fn counts_dependencies_behind_level() {
+        let conn = snapshot::fixtures();
+        let elm_json = application(
+            "0.19.1",
+            r#"{ "elm/core": "1.0.0", "elm/json": "1.0.0", "elm/http": "1.0.0" }"#,
+        );
+
+        let minor = report(&conn, &elm_json, &threshold(Some(Lag::Minor), Some(1)));
+        assert_eq!(minor.outdated, 2);
+        assert!(minor.exceeded);
+        let major = report(&conn, &elm_json, &threshold(Some(Lag::Major), Some(1)));
+        assert_eq!(major.outdated, 1);
+        assert!(!major.exceeded);
+    }
+
+    #[test]
+    fn level_alone_allows_no_outdated_dependency() {
+        let conn = snapshot::fixtures();
+        let outdated = application("0.19.1", r#"{ "elm/http": "1.0.0" }"#);
+        assert!(report(&conn, &outdated, &threshold(Some(Lag::Major), None)).exceeded);
+        assert!(!report(&conn, &outdated, &threshold(None, None)).exceeded);
+
+        let patch = application("0.19.1", r#"{ "elm/core": "1.0.0" }"#);
+        assert!(!report(&conn, &patch, &threshold(Some(Lag::Minor), None)).exceeded);
+    }
+
+    #[test]
+    fn checks_elm_version_support() {
+        let conn = snapshot::fixtures();
+        let elm_json = application("0.18.0", r#"{ "elm/json": "1.1.2" }"#);
+        let report = report(&conn, &elm_json, &threshold(None, None));
+
+        let json = &report.dependencies[0];
+        assert_eq!(json.lag, Some(Lag::Patch));
+        assert!(!json.latest_supports_elm);
+        assert_eq!(json.newest_compatible, None);
+    }
+
+    #[test]
+    fn resolves_package_constraints() {
+        let conn = snapshot::fixtures();
+        let releases = db::package_releases(&conn, "elm/json");
+        let elm = "0.19.0 <= v < 0.20.0".parse().ok();
+
+        let within = dependency(
+            "elm/json".to_string(),
+            "1.0.0 <= v < 2.0.0".to_string(),
+            &releases,
+            &elm,
+        );
+        assert_eq!(within.current, Some(Version::new(1, 1, 3)));
+        assert_eq!(within.lag, None);
+
+        let missing = dependency(
+            "elm/json".to_string(),
+            "2.0.0 <= v < 3.0.0".to_string(),
+            &releases,
+            &elm,
+        );
+        assert_eq!(missing.current, None);
+        assert_eq!(missing.lag, Some(Lag::Major));
+        assert!(!missing.latest_in_constraint);
+    }
+}
@@ -0,0 +1,383 @@
+use crate::db;
+#[cfg(test)]
+use crate::db::models::Package;
+use crate::db::models::{NewPackage, PackageChanges};
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+/// Corrections of bad upstream data, declared in a JSON file:
+///
+///     [
+///       { "action": "add", "package": "alex-tan/loadable", "version": "1.0.0",
+///         "timestamp": 1540223747, "summary": "...", "license": "MIT",
+///         "elm_version": "0.19.0 <= v < 0.20.0", "dependencies": { ... } },
+///       { "action": "hide", "package": "author/project", "version": "1.0.0" },
+///       { "action": "patch", "package": "author/project", "version": "1.0.0",
+///         "summary": "Fixed summary" }
+///     ]
+///
+/// Hidden and patched releases are matched in all formats unless "format"
+/// is given. Added and patched releases are marked as corrected, and
+/// releases already published upstream can't be added.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Override {
+    Add {
+        package: String,
+        version: String,
+        timestamp: i64,
+        summary: String,
+        license: String,
+        elm_version: String,
+        dependencies: HashMap<String, String>,
+        #[serde(default = "default_format")]
+        format: i32,
+    },
+    Hide {
+        package: String,
+        version: String,
+        format: Option<i32>,
+    },
+    Patch {
+        package: String,
+        version: String,
+        format: Option<i32>,
+        timestamp: Option<i64>,
+        summary: Option<String>,
+        license: Option<String>,
+        elm_version: Option<String>,
+    },
+}
+
+fn default_format() -> i32 {
+    19
+}
+
+pub fn load(path: &str) -> Result<Vec<Override>, String> {
+    fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|s| parse(&s))
+}
+
+/// Parse overrides, rejecting patches without any field to patch
+pub fn parse(json: &str) -> Result<Vec<Override>, String> {
+    let overrides: Vec<Override> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    for o in &overrides {
+        if let Override::Patch {
+            package,
+            version,
+            timestamp: None,
+            summary: None,
+            license: None,
+            elm_version: None,
+            ..
+        } = o
+        {
+            return Err(format!("patch of {} {} has no fields", package, version));
+        }
+    }
+    Ok(overrides)
+}
+
+/// Upstream values of the fields of a release patched by overrides
+#[derive(Default, Serialize, Deserialize)]
+struct Original {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elm_version: Option<String>,
+}
+
+impl Original {
+    fn is_empty(&self) -> bool {
+        self.timestamp.is_none()
+            && self.summary.is_none()
+            && self.license.is_none()
+            && self.elm_version.is_none()
+    }
+}
+
+/// Load and apply the overrides file, which can be edited while running.
+pub fn apply_file(conn: &SqliteConnection, path: &str) -> Result<(), String> {
+    let overrides = load(path).map_err(|err| format!("can't load overrides {}: {}", path, err))?;
+    apply_all(conn, &overrides)
+}
+
+/// Recompute the overrides of all releases from the whole file: releases
+/// are reverted to their upstream values before applying each override, so
+/// that removed entries don't apply anymore.
+pub fn apply_all(conn: &SqliteConnection, overrides: &[Override]) -> Result<(), String> {
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        revert(conn)?;
+        for o in overrides {
+            apply(conn, o)?;
+        }
+        Ok(())
+    })
+    .map_err(|err| format!("can't apply overrides: {}", err))
+}
+
+/// Restore the upstream values of patched releases and clear the flags set
+/// by overrides, hiding added releases.
+fn revert(conn: &SqliteConnection) -> QueryResult<()> {
+    for pkg in db::patched_packages(conn) {
+        let original: Original = pkg
+            .original
+            .as_deref()
+            .and_then(|values| serde_json::from_str(values).ok())
+            .unwrap_or_default();
+        if original.is_empty() {
+            continue;
+        }
+        let changes = PackageChanges {
+            timestamp: original.timestamp.as_ref(),
+            summary: original.summary.as_deref(),
+            license: original.license.as_deref(),
+            elm_version: original.elm_version.as_deref(),
+            ..Default::default()
+        };
+        db::update_package_id(conn, pkg.id, &changes)?;
+    }
+    db::reset_overrides(conn);
+    Ok(())
+}
+
+pub fn apply(conn: &SqliteConnection, o: &Override) -> QueryResult<()> {
+    match o {
+        Override::Add {
+            package,
+            version,
+            timestamp,
+            summary,
+            license,
+            elm_version,
+            dependencies,
+            format,
+        } => {
+            let repo: Vec<&str> = package.split('/').collect();
+            let semver: Vec<i32> = version.split('.').filter_map(|s| s.parse().ok()).collect();
+            if let ([author, name], [major, minor, patch]) = (&repo[..], &semver[..]) {
+                let dependencies = serde_json::json!(dependencies).to_string();
+                let existing = db::package_versions(conn, package, version, Some(*format));
+                if existing.iter().any(|pkg| !pkg.added) {
+                    log::warn!(
+                        package = package.as_str(), version = version.as_str();
+                        "Ignoring addition of {} {} already published upstream", package, version
+                    );
+                    return Ok(());
+                }
+                if existing.is_empty() {
+                    db::save_package(
+                        conn,
+                        &NewPackage {
+                            timestamp,
+                            major: *major,
+                            minor: *minor,
+                            patch: *patch,
+                            author,
+                            name,
+                            summary,
+                            license,
+                            elm_version,
+                            dependencies: &dependencies,
+                            format: *format,
+                        },
+                    );
+                }
+                let changes = PackageChanges {
+                    timestamp: Some(timestamp),
+                    summary: Some(summary),
+                    license: Some(license),
+                    elm_version: Some(elm_version),
+                    dependencies: Some(&dependencies),
+                    hidden: Some(false),
+                    corrected: Some(true),
+                    added: Some(true),
+                    ..Default::default()
+                };
+                db::update_package(conn, package, version, Some(*format), &changes);
+            } else {
+                log::error!(
+                    package = package.as_str(), version = version.as_str();
+                    "Ignoring invalid override of {} {}", package, version
+                );
+            }
+        }
+        Override::Hide {
+            package,
+            version,
+            format,
+        } => {
+            let changes = PackageChanges {
+                hidden: Some(true),
+                ..Default::default()
+            };
+            if db::update_package(conn, package, version, *format, &changes) == 0 {
+                log::warn!(
+                    package = package.as_str(), version = version.as_str();
+                    "Can't hide missing package {} {}", package, version
+                );
+            }
+        }
+        Override::Patch {
+            package,
+            version,
+            format,
+            timestamp,
+            summary,
+            license,
+            elm_version,
+        } => {
+            let pkgs = db::package_versions(conn, package, version, *format);
+            if pkgs.is_empty() {
+                log::warn!(
+                    package = package.as_str(), version = version.as_str();
+                    "Can't patch missing package {} {}", package, version
+                );
+            }
+            for pkg in pkgs {
+                // Keep the values saved by previous patches of the release
+                let mut original: Original = pkg
+                    .original
+                    .as_deref()
+                    .and_then(|values| serde_json::from_str(values).ok())
+                    .unwrap_or_default();
+                if timestamp.is_some() && original.timestamp.is_none() {
+                    original.timestamp = Some(pkg.timestamp);
+                }
+                if summary.is_some() && original.summary.is_none() {
+                    original.summary = Some(pkg.summary);
+                }
+                if license.is_some() && original.license.is_none() {
+                    original.license = Some(pkg.license);
+                }
+                if elm_version.is_some() && original.elm_version.is_none() {
+                    original.elm_version = Some(pkg.elm_version);
+                }
+                let original = serde_json::to_string(&original).expect("Can't serialize original");
+                db::set_original(conn, pkg.id, Some(&original));
+
+                let changes = PackageChanges {
+                    timestamp: timestamp.as_ref(),
+                    summary: summary.as_deref(),
+                    license: license.as_deref(),
+                    elm_version: elm_version.as_deref(),
+                    corrected: Some(true),
+                    ..Default::default()
+                };
+                db::update_package_id(conn, pkg.id, &changes)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elm::snapshot;
+
+    fn overrides(json: &str) -> Vec<Override> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn json(conn: &SqliteConnection) -> Package {
+        db::package_versions(conn, "elm/json", "1.1.3", None).remove(0)
+    }
+
+    #[test]
+    fn removed_entries_are_reverted() {
+        let conn = snapshot::fixtures();
+        apply_all(
+            &conn,
+            &overrides(
+                r#"[
+                  { "action": "hide", "package": "elm/json", "version": "1.1.3" },
+                  { "action": "patch", "package": "elm/json", "version": "1.1.3",
+                    "summary": "Patched", "license": "MIT" },
+                  { "action": "patch", "package": "elm/json", "version": "1.1.3",
+                    "summary": "Patched again" }
+                ]"#,
+            ),
+        )
+        .unwrap();
+        let pkg = json(&conn);
+        assert!(pkg.hidden);
+        assert!(pkg.corrected);
+        assert_eq!(pkg.summary, "Patched again");
+        assert_eq!(pkg.license, "MIT");
+
+        apply_all(&conn, &[]).unwrap();
+        let pkg = json(&conn);
+        assert!(!pkg.hidden);
+        assert!(!pkg.corrected);
+        assert_eq!(pkg.license, "BSD-3-Clause");
+        assert_eq!(pkg.original, None);
+        let fixture = snapshot::fixtures();
+        assert_eq!(pkg.summary, json(&fixture).summary);
+    }
+
+    #[test]
+    fn empty_patches_are_applied_again() {
+        let conn = snapshot::fixtures();
+        let empty = r#"[{ "action": "patch", "package": "elm/json", "version": "1.1.3" }]"#;
+        assert!(parse(empty).is_err());
+
+        let overrides = overrides(empty);
+        apply_all(&conn, &overrides).unwrap();
+        apply_all(&conn, &overrides).unwrap();
+        assert!(json(&conn).corrected);
+    }
+
+    #[test]
+    fn added_releases_are_hidden_when_removed() {
+        let conn = snapshot::fixtures();
+        let add = overrides(
+            r#"[
+              { "action": "add", "package": "elm/json", "version": "1.1.4",
+                "timestamp": 1600000000, "summary": "Added", "license": "MIT",
+                "elm_version": "0.19.0 <= v < 0.20.0", "dependencies": {} }
+            ]"#,
+        );
+        apply_all(&conn, &add).unwrap();
+        let pkg = db::package_versions(&conn, "elm/json", "1.1.4", None).remove(0);
+        assert!(pkg.added);
+        assert!(pkg.corrected);
+        assert!(!pkg.hidden);
+
+        apply_all(&conn, &[]).unwrap();
+        let pkg = db::package_versions(&conn, "elm/json", "1.1.4", None).remove(0);
+        assert!(pkg.hidden);
+        assert!(!pkg.corrected);
+
+        apply_all(&conn, &add).unwrap();
+        assert!(!db::package_versions(&conn, "elm/json", "1.1.4", None)[0].hidden);
+    }
+
+    #[test]
+    fn adding_upstream_releases_is_ignored() {
+        let conn = snapshot::fixtures();
+        apply_all(
+            &conn,
+            &overrides(
+                r#"[
+                  { "action": "add", "package": "elm/json", "version": "1.1.3",
+                    "timestamp": 1600000000, "summary": "Added", "license": "MIT",
+                    "elm_version": "0.19.0 <= v < 0.20.0", "dependencies": {} }
+                ]"#,
+            ),
+        )
+        .unwrap();
+        let pkg = json(&conn);
+        assert!(!pkg.corrected);
+        assert!(!pkg.added);
+        assert_eq!(pkg.license, "BSD-3-Clause");
+    }
+}
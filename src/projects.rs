@@ -0,0 +1,146 @@
+use crate::db;
+use crate::db::models::{Package, Project};
+use crate::elm::project::{self, ElmJson};
+use crate::elm::version::{Constraint, Version};
+use crate::release::Release;
+use crate::rss;
+use chrono::Utc;
+use diesel::sqlite::SqliteConnection;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+
+/// Number of hexadecimal characters of project ids
+const ID_LENGTH: usize = 12;
+
+/// A release newer than the version pinned by an application
+pub struct Upgrade {
+    pub package: Package,
+    pub from: Version,
+    /// Whether the major version changes
+    pub breaking: bool,
+}
+
+/// Save an uploaded elm.json. Its id is a hash of the normalized JSON, so
+/// that uploading the same elm.json again returns the same id.
+pub fn create(conn: &SqliteConnection, body: &str) -> Result<Project, String> {
+    project::parse(body)?;
+    let normalized = serde_json::from_str::<serde_json::Value>(body)
+        .map_err(|err| format!("invalid elm.json: {}", err))?
+        .to_string();
+    let id = hex::encode(Sha256::digest(normalized.as_bytes()))[..ID_LENGTH].to_string();
+
+    let project = Project {
+        id,
+        elm_json: normalized,
+        created: Utc::now().timestamp(),
+    };
+    db::projects::save(conn, &project);
+    Ok(project)
+}
+
+/// Last releases of the packages of a feed filter, none when it is empty
+/// since an empty filter would match all packages
+pub fn last_releases(
+    conn: &SqliteConnection,
+    filter: HashMap<String, String>,
+    release: &Release,
+    limit: i64,
+) -> Vec<Package> {
+    if filter.is_empty() {
+        return Vec::new();
+    }
+    db::last_packages(conn, filter, release, limit)
+}
+
+pub fn feed_url(id: &str) -> String {
+    format!("{}/p/{}/.rss", rss::base_url(), id)
+}
+
+pub fn upgrades_url(id: &str) -> String {
+    format!("{}/p/{}/upgrades/.rss", rss::base_url(), id)
+}
+
+/// Direct and indirect dependencies of a project. Those of applications are
+/// listed in their elm.json, those of packages are resolved through the
+/// dependencies of the latest release of each dependency.
+pub fn packages(conn: &SqliteConnection, project: &Project) -> BTreeSet<String> {
+    match project::parse(&project.elm_json) {
+        Ok(elm_json @ ElmJson::Application(_)) => elm_json.dependencies(),
+        Ok(elm_json @ ElmJson::Package(_)) => db::dependency_closure(conn, elm_json.dependencies()),
+        Err(_) => BTreeSet::new(),
+    }
+}
+
+/// Feed filter of packages, e.g. {"elm": "core json"} for elm/core and elm/json
+pub fn filter(packages: &BTreeSet<String>) -> HashMap<String, String> {
+    let mut filter: HashMap<String, String> = HashMap::new();
+    for package in packages {
+        if let Some((author, name)) = package.split_once('/') {
+            filter
+                .entry(author.to_string())
+                .and_modify(|names| {
+                    names.push(' ');
+                    names.push_str(name);
+                })
+                .or_insert_with(|| name.to_string());
+        }
+    }
+    filter
+}
+
+/// Whether a project is an application, as opposed to a package
+pub fn is_application(project: &Project) -> bool {
+    matches!(
+        project::parse(&project.elm_json),
+        Ok(ElmJson::Application(_))
+    )
+}
+
+/// Releases newer than the versions pinned by an application, supporting
+/// its elm version, from the newest. None for packages, which don't pin
+/// versions.
+pub fn upgrades(conn: &SqliteConnection, project: &Project) -> Option<Vec<Upgrade>> {
+    let app = match project::parse(&project.elm_json) {
+        Ok(ElmJson::Application(app)) => app,
+        _ => return None,
+    };
+    let elm_version = app.elm_version.parse::<Version>().ok();
+    let pins = app
+        .dependencies
+        .direct
+        .iter()
+        .chain(app.dependencies.indirect.iter());
+
+    let mut upgrades = Vec::new();
+    for (repo, pin) in pins {
+        let from = match pin.parse::<Version>() {
+            Ok(from) => from,
+            Err(_) => continue,
+        };
+        for package in db::package_releases(conn, repo) {
+            let version = Version::new(package.major, package.minor, package.patch);
+            if version <= from || !supports(&package, &elm_version) {
+                continue;
+            }
+            upgrades.push(Upgrade {
+                breaking: version.major > from.major,
+                package,
+                from,
+            });
+        }
+    }
+    upgrades.sort_by_key(|upgrade| std::cmp::Reverse(upgrade.package.timestamp));
+    Some(upgrades)
+}
+
+/// Whether a release supports an elm version, when known
+fn supports(package: &Package, elm_version: &Option<Version>) -> bool {
+    match elm_version {
+        Some(elm_version) => package
+            .elm_version
+            .parse::<Constraint>()
+            .map(|constraint| constraint.contains(elm_version))
+            .unwrap_or(false),
+        None => true,
+    }
+}
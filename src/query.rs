@@ -0,0 +1,73 @@
+use chrono::{DateTime, NaiveDate};
+use std::collections::HashMap;
+
+/// Sort authors and packages of a feed query so that equivalent queries are
+/// equal, e.g. "elm=json+core&author=a" and "author=a&elm=core+json".
+/// Packages are separated by spaces, as "+" is decoded in query strings.
+pub fn normalize(query: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = query
+        .iter()
+        .map(|(key, value)| {
+            if key.starts_with('_') {
+                (key.clone(), value.clone())
+            } else {
+                let mut names: Vec<&str> = value.split(' ').collect();
+                names.sort_unstable();
+                names.dedup();
+                (key.clone(), names.join(" "))
+            }
+        })
+        .collect();
+    fields.sort();
+    fields
+}
+
+/// Encode a normalized query string, without the leading "?"
+pub fn encode(query: &HashMap<String, String>) -> String {
+    serde_urlencoded::to_string(normalize(query)).expect("Can't encode query")
+}
+
+pub fn decode(query: &str) -> Result<HashMap<String, String>, String> {
+    serde_urlencoded::from_str(query).map_err(|err| err.to_string())
+}
+
+/// Timestamp of a `_at` date, either RFC 3339 or a day, e.g. "2020-01-31"
+/// which includes releases of the whole day (UTC)
+pub fn timestamp(date: &str) -> Result<i64, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Ok(datetime.timestamp());
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(23, 59, 59))
+        .map(|datetime| datetime.timestamp())
+        .ok_or_else(|| format!("invalid date {}", date))
+}
+
+/// Check the options of a feed query that would be ignored when invalid
+pub fn validate(query: &HashMap<String, String>) -> Result<(), String> {
+    match query.get("_at") {
+        Some(date) => timestamp(date).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_days_and_rfc3339_dates() {
+        assert_eq!(timestamp("2020-01-31"), Ok(1580515199));
+        assert_eq!(timestamp("2020-01-31T00:00:00Z"), Ok(1580428800));
+        assert!(timestamp("2020-02-30").is_err());
+        assert!(timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert!(validate(&decode("elm=core").unwrap()).is_ok());
+        assert!(validate(&decode("elm=core&_at=2020-01-31").unwrap()).is_ok());
+        assert!(validate(&decode("elm=core&_at=2020-13-01").unwrap()).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 pub enum Release {
     Any,
     Last,
@@ -6,3 +8,33 @@ pub enum Release {
     Minor,
     Patch,
 }
+
+impl Release {
+    /// Path of the feed route, e.g. "/last/.rss"
+    pub fn route(&self) -> &'static str {
+        match self {
+            Release::Any => "/.rss",
+            Release::Last => "/last/.rss",
+            Release::First => "/first/.rss",
+            Release::Major => "/major/.rss",
+            Release::Minor => "/minor/.rss",
+            Release::Patch => "/patch/.rss",
+        }
+    }
+}
+
+impl FromStr for Release {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Release, String> {
+        match s {
+            "any" => Ok(Release::Any),
+            "last" => Ok(Release::Last),
+            "first" => Ok(Release::First),
+            "major" => Ok(Release::Major),
+            "minor" => Ok(Release::Minor),
+            "patch" => Ok(Release::Patch),
+            _ => Err(format!("unknown release kind {}", s)),
+        }
+    }
+}
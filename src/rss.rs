@@ -1,37 +1,115 @@
 use crate::db;
 use crate::db::models::Package;
 use crate::elm;
+use crate::history;
+use crate::projects::{self, Upgrade};
 use crate::release::Release;
+use crate::stale;
 use chrono::{TimeZone, Utc};
+use diesel::sqlite::SqliteConnection;
+use rss::extension::{Extension, ExtensionMap};
 use rss::*;
 use std::collections::HashMap;
+use std::env;
 
-pub fn all(user_agent: String, query: HashMap<String, String>, release: &Release) -> String {
-    let conn = db::connect();
+/// Public URL of this instance, from the BASE_URL environment variable
+pub fn base_url() -> String {
+    env::var("BASE_URL").unwrap_or("https://releases.elm.dmy.fr".to_string())
+}
+
+pub fn all(
+    conn: &SqliteConnection,
+    user_agent: String,
+    query: HashMap<String, String>,
+    release: &Release,
+    self_url: &str,
+) -> String {
     let title = channel_title(&query, release);
-    let packages = db::last_packages(&conn, query, release, 42);
-    let items: Vec<Item> = packages
+    let packages = db::last_packages(conn, query, release, 42);
+    let stale = stale::annotations(conn, &packages, &stale::criteria());
+    let items = packages
         .iter()
-        .map(|pkg| item(&user_agent, pkg))
-        .filter_map(Result::ok)
-        .collect();
-    let last_timestamp = packages
+        .map(|pkg| annotate(item(&user_agent, pkg), pkg, &stale));
+    channel(&title, items, newest(&packages), release, self_url)
+}
+
+/// Releases of the dependencies of an uploaded elm.json
+pub fn project(
+    conn: &SqliteConnection,
+    user_agent: String,
+    id: &str,
+    filter: HashMap<String, String>,
+    release: &Release,
+    self_url: &str,
+) -> String {
+    let title = format!("Elm packages releases of project {}", id);
+    let packages = projects::last_releases(conn, filter, release, 42);
+    let stale = stale::annotations(conn, &packages, &stale::criteria());
+    let items = packages
         .iter()
-        .map(|item| item.timestamp)
-        .max()
-        .unwrap_or(Utc::now().timestamp());
+        .map(|pkg| annotate(item(&user_agent, pkg), pkg, &stale));
+    channel(&title, items, newest(&packages), release, self_url)
+}
+
+/// Releases newer than the versions pinned by an uploaded application
+pub fn upgrades(user_agent: String, id: &str, upgrades: &[Upgrade], self_url: &str) -> String {
+    let title = format!("Elm packages upgrades of project {}", id);
+    let upgrades = &upgrades[..upgrades.len().min(42)];
+    let last_timestamp = upgrades.iter().map(|u| u.package.timestamp).max();
+    let items = upgrades.iter().map(|upgrade| {
+        let kind = if upgrade.breaking {
+            "breaking"
+        } else {
+            "within major"
+        };
+        let mut item = item(&user_agent, &upgrade.package)?;
+        item.set_title(format!(
+            "{} ({} upgrade from {})",
+            item_title(&upgrade.package),
+            kind,
+            upgrade.from
+        ));
+        let mut categories = item.categories().to_vec();
+        categories.push(category("upgrade", kind));
+        item.set_categories(categories);
+        Ok(item)
+    });
+    channel(&title, items, last_timestamp, &Release::Any, self_url)
+}
+
+fn newest(packages: &[Package]) -> Option<i64> {
+    packages.iter().map(|pkg| pkg.timestamp).max()
+}
+
+fn channel<I>(
+    title: &str,
+    items: I,
+    last_timestamp: Option<i64>,
+    release: &Release,
+    self_url: &str,
+) -> String
+where
+    I: Iterator<Item = Result<Item, String>>,
+{
+    let items: Vec<Item> = items.filter_map(Result::ok).collect();
+    let last_timestamp = last_timestamp.unwrap_or(Utc::now().timestamp());
 
     let mut namespaces = HashMap::new();
     namespaces.insert(
         "content".to_string(),
         "http://purl.org/rss/1.0/modules/content/".to_string(),
     );
+    namespaces.insert(
+        "atom".to_string(),
+        "http://www.w3.org/2005/Atom".to_string(),
+    );
 
     ChannelBuilder::default()
         .namespaces(namespaces)
-        .title(&title)
-        .link("https://releases.elm.dmy.fr")
-        .description(format!("{}", &title))
+        .extensions(channel_links(self_url))
+        .title(title)
+        .link(base_url())
+        .description(title)
         .image(channel_image())
         .pub_date(Utc.timestamp(last_timestamp, 0).to_rfc2822())
         .language("en-us".to_string())
@@ -42,7 +120,7 @@ pub fn all(user_agent: String, query: HashMap<String, String>, release: &Release
         .to_string()
 }
 
-fn channel_title(query: &HashMap<String, String>, release: &Release) -> String {
+pub fn channel_title(query: &HashMap<String, String>, release: &Release) -> String {
     let release_type = match release {
         Release::Any => "releases",
         Release::Last => "last release",
@@ -52,13 +130,20 @@ fn channel_title(query: &HashMap<String, String>, release: &Release) -> String {
         Release::Patch => "patch releases",
     };
     let pkgs = query.iter().fold(vec![], |mut pkgs, (author, names)| {
-        if author != "_search" {
+        if !author.starts_with('_') {
             pkgs.push(format!("{}/{}", author, str::replace(names, " ", "+")));
         }
         pkgs
     });
 
-    match (pkgs.is_empty(), query.get("_search")) {
+    let pkgs = match query.get("_transitive").map(String::as_str) {
+        Some("1") if !pkgs.is_empty() => {
+            vec![format!("{} and their dependencies", pkgs.join(", "))]
+        }
+        _ => pkgs,
+    };
+
+    let title = match (pkgs.is_empty(), query.get("_search")) {
         (true, None) => format!("Elm packages {}", &release_type),
         (true, Some(pattern)) => format!("Elm packages {} matching {}", &release_type, pattern),
         (false, None) => format!("Elm packages {} of {}", &release_type, pkgs.join(", ")),
@@ -68,18 +153,46 @@ fn channel_title(query: &HashMap<String, String>, release: &Release) -> String {
             pkgs.join(", "),
             pattern
         ),
+    };
+
+    match query.get("_at") {
+        Some(date) => format!("{} as of {}", title, date),
+        None => title,
     }
 }
 
 fn channel_image() -> Option<Image> {
     ImageBuilder::default()
         .title("Elm logo")
-        .link("https://releases.elm.dmy.fr")
-        .url("https://releases.elm.dmy.fr")
+        .link(base_url())
+        .url(base_url())
         .build()
         .ok()
 }
 
+/// Atom links to the feed itself and to its WebSub hub
+fn channel_links(self_url: &str) -> ExtensionMap {
+    let links = vec![
+        atom_link("self", self_url),
+        atom_link("hub", &crate::websub::hub_url()),
+    ];
+    let mut atom = HashMap::new();
+    atom.insert("atom:link".to_string(), links);
+    let mut extensions = ExtensionMap::new();
+    extensions.insert("atom".to_string(), atom);
+    extensions
+}
+
+fn atom_link(rel: &str, href: &str) -> Extension {
+    let mut attrs = HashMap::new();
+    attrs.insert("rel".to_string(), rel.to_string());
+    attrs.insert("href".to_string(), href.to_string());
+    let mut link = Extension::default();
+    link.set_name("atom:link");
+    link.attrs = attrs;
+    link
+}
+
 fn channel_categories(release: &Release) -> Vec<Category> {
     let location = match release {
         Release::Any => "Elm/Packages/Releases",
@@ -92,6 +205,82 @@ fn channel_categories(release: &Release) -> Vec<Category> {
     vec![category(elm::PACKAGES_URL, location)]
 }
 
+/// Every release of a package, annotated with changes since the previous one
+pub fn history(
+    conn: &SqliteConnection,
+    user_agent: String,
+    repo: &str,
+    releases: &[Package],
+    self_url: &str,
+) -> String {
+    let title = format!("Elm package {} releases", repo);
+    let stale = stale::annotations(conn, releases, &stale::criteria());
+    let items = releases
+        .iter()
+        .enumerate()
+        .rev()
+        .take(42)
+        .map(|(i, package)| {
+            let previous = i.checked_sub(1).map(|i| &releases[i]);
+            let mut item = annotate(item(&user_agent, package), package, &stale)?;
+            let mut categories = item.categories().to_vec();
+            for note in history::notes(previous, package) {
+                categories.push(category("history", &note));
+            }
+            item.set_categories(categories);
+            Ok(item)
+        });
+    channel(&title, items, newest(releases), &Release::Any, self_url)
+}
+
+/// Latest releases of stale packages, annotated with the reasons why they
+/// are stale
+pub fn stale(conn: &SqliteConnection, user_agent: String, self_url: &str) -> String {
+    let (latest, stale) = stale_releases(conn);
+    let items = latest
+        .iter()
+        .map(|pkg| annotate(item(&user_agent, pkg), pkg, &stale));
+    channel(
+        "Stale Elm packages",
+        items,
+        newest(&latest),
+        &Release::Last,
+        self_url,
+    )
+}
+
+/// Latest releases of stale packages served by the stale feed, newest
+/// first, with the reasons why they are stale
+pub fn stale_releases(
+    conn: &SqliteConnection,
+) -> (Vec<Package>, HashMap<String, Vec<stale::Reason>>) {
+    let mut latest = db::latest_releases(conn, HashMap::new(), None);
+    let stale = stale::reasons(conn, &latest, &stale::criteria());
+    latest.retain(|pkg| stale.contains_key(&format!("{}/{}", pkg.author, pkg.name)));
+    latest.sort_by_key(|pkg| std::cmp::Reverse(pkg.timestamp));
+    latest.truncate(42);
+    (latest, stale)
+}
+
+/// Add the reasons why the package of an item is stale as categories
+fn annotate(
+    item: Result<Item, String>,
+    package: &Package,
+    stale: &HashMap<String, Vec<stale::Reason>>,
+) -> Result<Item, String> {
+    let mut item = item?;
+    if let Some(reasons) = stale.get(&format!("{}/{}", package.author, package.name)) {
+        let mut categories = item.categories().to_vec();
+        categories.extend(
+            reasons
+                .iter()
+                .map(|reason| category("stale", reason.name())),
+        );
+        item.set_categories(categories);
+    }
+    Ok(item)
+}
+
 fn item(user_agent: &String, package: &Package) -> Result<Item, String> {
     ItemBuilder::default()
         .title(item_title(package))
@@ -125,7 +314,7 @@ fn is_new_package(package: &Package) -> bool {
     }
 }
 
-fn item_link(package: &Package) -> String {
+pub fn item_link(package: &Package) -> String {
     format!(
         "https://package.elm-lang.org/packages/{author}/{name}/{major}.{minor}.{patch}/",
         author = package.author,
@@ -160,7 +349,7 @@ fn item_description(user_agent: &String, package: &Package) -> String {
     }
 }
 
-fn is_slack(user_agent: &String) -> bool {
+pub fn is_slack(user_agent: &str) -> bool {
     if user_agent.contains("Slackbot") {
         return true;
     } else {
@@ -213,6 +402,9 @@ fn item_categories(package: &Package) -> Vec<Category> {
         &format!("elm {}", &package.elm_version.replace("<=", "≤")),
     ));
     categories.push(category("license", &package.license));
+    if package.corrected {
+        categories.push(category("greenwood", "corrected"));
+    }
     categories.sort_by(|c1, c2| c1.name().partial_cmp(c2.name()).unwrap());
     categories
 }
@@ -0,0 +1,172 @@
+use crate::api::PackageRelease;
+use crate::db;
+use crate::db::models::Package;
+use crate::elm::version::{Constraint, Version};
+use crate::projects;
+use chrono::Utc;
+use diesel::sqlite::SqliteConnection;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+
+/// Packages without release for longer are inactive, unless `STALE_DAYS`
+/// is set
+const STALE_DAYS: i64 = 730;
+
+/// Current compiler line, unless `ELM_VERSION` is set
+const ELM_VERSION: &str = "0.19.1";
+
+pub struct Criteria {
+    /// Seconds since the latest release
+    pub max_age: i64,
+    pub elm_version: Version,
+}
+
+/// Criteria given by the `STALE_DAYS` and `ELM_VERSION` environment
+/// variables
+pub fn criteria() -> Criteria {
+    let days = env::var("STALE_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(STALE_DAYS);
+    let elm_version = env::var("ELM_VERSION")
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .unwrap_or_else(|| ELM_VERSION.parse().expect("Can't parse ELM_VERSION"));
+    Criteria {
+        max_age: days * 86400,
+        elm_version,
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// The latest release is older than the threshold
+    Inactive,
+    /// The latest release doesn't support the current compiler line
+    UnsupportedElm,
+    /// The latest release depends on packages removed upstream
+    RemovedDependency,
+}
+
+impl Reason {
+    pub fn name(self) -> &'static str {
+        match self {
+            Reason::Inactive => "inactive",
+            Reason::UnsupportedElm => "unsupported elm",
+            Reason::RemovedDependency => "removed dependency",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Stale<'a> {
+    pub package: String,
+    pub latest: PackageRelease<'a>,
+    pub reasons: Vec<Reason>,
+    pub removed_dependencies: Vec<String>,
+}
+
+/// Dependencies of latest releases whose own latest release was removed
+/// upstream
+pub fn removed_dependencies(conn: &SqliteConnection, latest: &[Package]) -> BTreeSet<String> {
+    let dependencies: BTreeSet<String> = latest.iter().flat_map(dependencies).collect();
+    if dependencies.is_empty() {
+        return BTreeSet::new();
+    }
+    db::latest_releases(conn, projects::filter(&dependencies), None)
+        .into_iter()
+        .filter(|package| package.removed)
+        .map(|package| format!("{}/{}", package.author, package.name))
+        .collect()
+}
+
+/// Stale packages among latest releases, from the most recently released
+pub fn list<'a>(
+    latest: &'a [Package],
+    removed: &BTreeSet<String>,
+    criteria: &Criteria,
+) -> Vec<Stale<'a>> {
+    let now = Utc::now().timestamp();
+    let mut latest: Vec<&Package> = latest.iter().collect();
+    latest.sort_by_key(|package| std::cmp::Reverse(package.timestamp));
+    latest
+        .into_iter()
+        .filter_map(|package| {
+            let (reasons, removed_dependencies) = check(package, removed, criteria, now);
+            if reasons.is_empty() {
+                return None;
+            }
+            Some(Stale {
+                package: format!("{}/{}", package.author, package.name),
+                latest: package.into(),
+                reasons,
+                removed_dependencies,
+            })
+        })
+        .collect()
+}
+
+/// Reasons why the packages of releases are stale, by "author/name"
+pub fn annotations(
+    conn: &SqliteConnection,
+    releases: &[Package],
+    criteria: &Criteria,
+) -> HashMap<String, Vec<Reason>> {
+    let repos: BTreeSet<String> = releases
+        .iter()
+        .map(|package| format!("{}/{}", package.author, package.name))
+        .collect();
+    if repos.is_empty() {
+        return HashMap::new();
+    }
+    let latest = db::latest_releases(conn, projects::filter(&repos), None);
+    reasons(conn, &latest, criteria)
+}
+
+/// Reasons why the packages of latest releases are stale, by "author/name"
+pub fn reasons(
+    conn: &SqliteConnection,
+    latest: &[Package],
+    criteria: &Criteria,
+) -> HashMap<String, Vec<Reason>> {
+    let removed = removed_dependencies(conn, latest);
+    list(latest, &removed, criteria)
+        .into_iter()
+        .map(|stale| (stale.package, stale.reasons))
+        .collect()
+}
+
+fn check(
+    package: &Package,
+    removed: &BTreeSet<String>,
+    criteria: &Criteria,
+    now: i64,
+) -> (Vec<Reason>, Vec<String>) {
+    let mut reasons = Vec::new();
+    if now - package.timestamp > criteria.max_age {
+        reasons.push(Reason::Inactive);
+    }
+    let supported = package
+        .elm_version
+        .parse::<Constraint>()
+        .map(|constraint| constraint.contains(&criteria.elm_version))
+        .unwrap_or(false);
+    if !supported {
+        reasons.push(Reason::UnsupportedElm);
+    }
+    let removed_dependencies: Vec<String> = dependencies(package)
+        .filter(|dependency| removed.contains(dependency))
+        .collect();
+    if !removed_dependencies.is_empty() {
+        reasons.push(Reason::RemovedDependency);
+    }
+    (reasons, removed_dependencies)
+}
+
+fn dependencies(package: &Package) -> impl Iterator<Item = String> {
+    serde_json::from_str::<HashMap<String, String>>(&package.dependencies)
+        .unwrap_or_default()
+        .into_keys()
+}
@@ -0,0 +1,148 @@
+use crate::db::models::Package;
+use chrono::{Datelike, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Length of the rankings of packages and authors
+const TOP: usize = 20;
+
+/// Aggregated statistics of all visible releases
+#[derive(Serialize)]
+pub struct Stats {
+    /// Keyed by ISO week, e.g. "2020-W03"
+    pub releases_per_week: BTreeMap<String, usize>,
+    /// Keyed by month, e.g. "2020-01"
+    pub releases_per_month: BTreeMap<String, usize>,
+    pub new_packages_per_month: BTreeMap<String, usize>,
+    /// Releases by kind, first releases being major releases too
+    pub releases_by_kind: BTreeMap<&'static str, usize>,
+    /// Packages with the most dependents among latest releases
+    pub most_depended_upon: Vec<Dependents>,
+    pub most_active_authors: Vec<Activity>,
+    /// Licenses of latest releases
+    pub licenses: BTreeMap<String, usize>,
+    /// Supported elm versions of latest releases
+    pub elm_versions: BTreeMap<String, usize>,
+}
+
+#[derive(Serialize)]
+pub struct Dependents {
+    pub package: String,
+    pub dependents: usize,
+}
+
+#[derive(Serialize)]
+pub struct Activity {
+    pub author: String,
+    pub releases: usize,
+    pub packages: usize,
+}
+
+/// Compute statistics of releases ordered by publication
+pub fn stats(releases: &[Package]) -> Stats {
+    let mut releases_per_week = BTreeMap::new();
+    let mut releases_per_month = BTreeMap::new();
+    let mut new_packages_per_month = BTreeMap::new();
+    let mut releases_by_kind = BTreeMap::new();
+    let mut latest: HashMap<(&str, &str), &Package> = HashMap::new();
+    let mut authors: HashMap<&str, (usize, Vec<&str>)> = HashMap::new();
+
+    for package in releases {
+        let date = Utc.timestamp_opt(package.timestamp, 0).unwrap();
+        let week = date.iso_week();
+        *releases_per_week
+            .entry(format!("{}-W{:02}", week.year(), week.week()))
+            .or_insert(0) += 1;
+        let month = date.format("%Y-%m").to_string();
+        *releases_per_month.entry(month.clone()).or_insert(0) += 1;
+
+        for kind in kinds(package) {
+            *releases_by_kind.entry(kind).or_insert(0) += 1;
+        }
+
+        let repo = (package.author.as_str(), package.name.as_str());
+        match latest.get(&repo) {
+            Some(other) if version(other) >= version(package) => (),
+            Some(_) => {
+                latest.insert(repo, package);
+            }
+            None => {
+                *new_packages_per_month.entry(month).or_insert(0) += 1;
+                latest.insert(repo, package);
+            }
+        }
+
+        let author = authors.entry(&package.author).or_default();
+        author.0 += 1;
+        if !author.1.contains(&package.name.as_str()) {
+            author.1.push(&package.name);
+        }
+    }
+
+    let mut dependents: HashMap<String, usize> = HashMap::new();
+    let mut licenses = BTreeMap::new();
+    let mut elm_versions = BTreeMap::new();
+    for package in latest.values() {
+        let dependencies: BTreeMap<String, String> =
+            serde_json::from_str(&package.dependencies).unwrap_or_default();
+        for dependency in dependencies.into_keys() {
+            *dependents.entry(dependency).or_insert(0) += 1;
+        }
+        *licenses.entry(package.license.clone()).or_insert(0) += 1;
+        *elm_versions.entry(package.elm_version.clone()).or_insert(0) += 1;
+    }
+
+    let mut most_depended_upon: Vec<Dependents> = dependents
+        .into_iter()
+        .map(|(package, dependents)| Dependents {
+            package,
+            dependents,
+        })
+        .collect();
+    most_depended_upon.sort_by(|a, b| {
+        b.dependents
+            .cmp(&a.dependents)
+            .then_with(|| a.package.cmp(&b.package))
+    });
+    most_depended_upon.truncate(TOP);
+
+    let mut most_active_authors: Vec<Activity> = authors
+        .into_iter()
+        .map(|(author, (releases, packages))| Activity {
+            author: author.to_string(),
+            releases,
+            packages: packages.len(),
+        })
+        .collect();
+    most_active_authors.sort_by(|a, b| {
+        b.releases
+            .cmp(&a.releases)
+            .then_with(|| a.author.cmp(&b.author))
+    });
+    most_active_authors.truncate(TOP);
+
+    Stats {
+        releases_per_week,
+        releases_per_month,
+        new_packages_per_month,
+        releases_by_kind,
+        most_depended_upon,
+        most_active_authors,
+        licenses,
+        elm_versions,
+    }
+}
+
+/// Kinds of a release, as the filters of `release::Release`
+fn kinds(package: &Package) -> Vec<&'static str> {
+    match (package.major, package.minor, package.patch) {
+        (1, 0, 0) => vec!["first", "major"],
+        (_, 0, 0) => vec!["major"],
+        (_, _, 0) => vec!["minor"],
+        _ => vec!["patch"],
+    }
+}
+
+fn version(package: &Package) -> (i32, i32, i32) {
+    (package.major, package.minor, package.patch)
+}
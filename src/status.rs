@@ -0,0 +1,75 @@
+use chrono::{TimeZone, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Number of recent synchronization errors reported
+const MAX_ERRORS: usize = 20;
+
+/// Synchronization state shared with the status endpoint
+#[derive(Clone, Default)]
+pub struct Status {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    last_sync: BTreeMap<&'static str, i64>,
+    errors: VecDeque<SyncError>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SyncError {
+    pub time: String,
+    pub source: &'static str,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub last_sync: BTreeMap<&'static str, String>,
+    pub releases: BTreeMap<i32, i64>,
+    pub newest_release: Option<String>,
+    pub errors: Vec<SyncError>,
+}
+
+impl Status {
+    /// Record the result of a synchronization from a source
+    pub fn record(&self, source: &'static str, result: Result<(), String>) {
+        let mut inner = self.inner.lock().unwrap();
+        match result {
+            Ok(()) => {
+                inner.last_sync.insert(source, Utc::now().timestamp());
+            }
+            Err(message) => {
+                log::error!(source = source; "{}", message);
+                if inner.errors.len() >= MAX_ERRORS {
+                    inner.errors.pop_front();
+                }
+                inner.errors.push_back(SyncError {
+                    time: Utc::now().to_rfc3339(),
+                    source,
+                    message,
+                });
+            }
+        }
+    }
+
+    pub fn report(&self, releases: BTreeMap<i32, i64>, newest_release: Option<i64>) -> Report {
+        let inner = self.inner.lock().unwrap();
+        Report {
+            last_sync: inner
+                .last_sync
+                .iter()
+                .map(|(source, time)| (*source, rfc3339(*time)))
+                .collect(),
+            releases,
+            newest_release: newest_release.map(rfc3339),
+            errors: inner.errors.iter().rev().cloned().collect(),
+        }
+    }
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0).unwrap().to_rfc3339()
+}
@@ -0,0 +1,113 @@
+use crate::api::PackageRelease;
+use crate::db;
+use crate::db::models::{NewWebhook, Webhook};
+use crate::query;
+use crate::release::Release;
+use crate::websub;
+use diesel::sqlite::SqliteConnection;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+
+/// Webhook registration of the admin API
+#[derive(Deserialize)]
+pub struct Registration {
+    url: String,
+    #[serde(default)]
+    filter: String,
+    #[serde(default = "any")]
+    release: String,
+    secret: Option<String>,
+}
+
+fn any() -> String {
+    "any".to_string()
+}
+
+/// JSON body POSTed to webhooks for each new release
+#[derive(Serialize)]
+struct Payload<'a> {
+    webhook: i32,
+    #[serde(flatten)]
+    release: PackageRelease<'a>,
+}
+
+/// Validate and save a webhook, generating a secret when none is given
+pub fn register(conn: &SqliteConnection, registration: Registration) -> Result<Webhook, String> {
+    if !registration.url.starts_with("http://") && !registration.url.starts_with("https://") {
+        return Err(format!("invalid url {}", registration.url));
+    }
+    query::decode(&registration.filter)
+        .and_then(|filter| query::validate(&filter))
+        .map_err(|err| format!("invalid filter {}: {}", registration.filter, err))?;
+    registration.release.parse::<Release>()?;
+    let secret = registration.secret.unwrap_or_else(|| {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect()
+    });
+
+    let webhook = db::webhooks::save(
+        conn,
+        &NewWebhook {
+            url: &registration.url,
+            filter: &registration.filter,
+            release: &registration.release,
+            secret: &secret,
+        },
+    );
+    log::info!(webhook = webhook.id, url = webhook.url.as_str(); "Registered webhook {}", webhook.url);
+    Ok(webhook)
+}
+
+/// POST releases inserted after the package with id `since` to webhooks
+/// with a matching filter. Failed deliveries are logged but not retried.
+pub fn deliver(conn: &SqliteConnection, since: i32) {
+    let client = websub::client();
+
+    for webhook in db::webhooks::all(conn) {
+        let (filter, release) = match (
+            query::decode(&webhook.filter),
+            webhook.release.parse::<Release>(),
+        ) {
+            (Ok(filter), Ok(release)) => (filter, release),
+            _ => continue,
+        };
+
+        for package in db::new_packages(conn, filter, &release, since) {
+            let body = serde_json::to_string(&Payload {
+                webhook: webhook.id,
+                release: PackageRelease::from(&package),
+            })
+            .expect("Can't serialize webhook payload");
+            let signature = websub::signature(&webhook.secret, body.as_bytes());
+            let version = format!("{}.{}.{}", package.major, package.minor, package.patch);
+            let repo = format!("{}/{}", package.author, package.name);
+
+            let result = client
+                .post(&webhook.url)
+                .header(CONTENT_TYPE, "application/json")
+                .header("X-Greenwood-Event", "release")
+                .header("X-Greenwood-Signature", format!("sha256={}", signature))
+                .body(body)
+                .send();
+            match result {
+                Ok(resp) if resp.status().is_success() => log::info!(
+                    webhook = webhook.id, package = repo.as_str(), version = version.as_str();
+                    "Delivered {} {} to {}", repo, version, webhook.url
+                ),
+                Ok(resp) => log::warn!(
+                    webhook = webhook.id, package = repo.as_str(), version = version.as_str();
+                    "Can't deliver {} {} to {}: {}", repo, version, webhook.url, resp.status()
+                ),
+                Err(err) => log::warn!(
+                    webhook = webhook.id, package = repo.as_str(), version = version.as_str();
+                    "Can't deliver {} {} to {}: {}", repo, version, webhook.url, err
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use crate::db;
+use crate::db::models::NewSubscription;
+use crate::projects;
+use crate::query;
+use crate::release::Release;
+use crate::rss;
+use chrono::Utc;
+use diesel::sqlite::SqliteConnection;
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use reqwest::header::{CONTENT_TYPE, LINK};
+use reqwest::{Client, RedirectPolicy, StatusCode, Url};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+/// Default and maximum subscription leases in seconds
+const DEFAULT_LEASE: i64 = 10 * 24 * 3600;
+const MAX_LEASE: i64 = 30 * 24 * 3600;
+
+/// Maximum length of a subscriber secret, as required by the WebSub spec
+const MAX_SECRET_LENGTH: usize = 200;
+
+/// Maximum number of subscription requests waiting for verification
+const MAX_PENDING: usize = 100;
+
+/// Timeout of requests to subscribers and webhooks
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Subscription request of a WebSub subscriber, from a POSTed form
+#[derive(Deserialize)]
+pub struct Request {
+    #[serde(rename = "hub.mode")]
+    mode: String,
+    #[serde(rename = "hub.callback")]
+    callback: String,
+    #[serde(rename = "hub.topic")]
+    topic: String,
+    #[serde(rename = "hub.lease_seconds")]
+    lease_seconds: Option<String>,
+    #[serde(rename = "hub.secret")]
+    secret: Option<String>,
+}
+
+/// Reason to refuse a subscription request
+pub enum Refusal {
+    Invalid(String),
+    /// Too many requests are waiting for verification
+    Busy,
+}
+
+/// Queue of subscription requests, verified one at a time by a worker
+#[derive(Clone)]
+pub struct Verifier(SyncSender<Request>);
+
+/// A feed served by this instance
+pub enum Topic {
+    /// Feeds filtered by a query, e.g. "/last/.rss?elm=core"
+    Query {
+        release: Release,
+        query: HashMap<String, String>,
+    },
+    /// Dependencies of a project, "/p/{id}/.rss"
+    Project(String),
+    /// Upgrades of the dependencies of an application, "/p/{id}/upgrades/.rss"
+    Upgrades(String),
+    /// Releases of a package, "/packages/{author}/{name}/.rss"
+    Package(String),
+    /// Latest releases of stale packages, "/stale/.rss"
+    Stale,
+}
+
+pub fn hub_url() -> String {
+    format!("{}/websub", rss::base_url())
+}
+
+/// Canonical URL of a feed, used as its WebSub topic
+pub fn topic_url(route: &str, query: &HashMap<String, String>) -> String {
+    match query::encode(query).as_str() {
+        "" => format!("{}{}", rss::base_url(), route),
+        q => format!("{}{}?{}", rss::base_url(), route, q),
+    }
+}
+
+/// Parse a feed URL of this instance, e.g. "https://host/last/.rss?elm=core"
+/// or "https://host/p/0123456789ab/.rss"
+pub fn parse_topic(url: &str) -> Option<Topic> {
+    let path_query = url.strip_prefix(&rss::base_url())?;
+    let (path, q) = match path_query.find('?') {
+        Some(i) => (&path_query[..i], &path_query[i + 1..]),
+        None => (path_query, ""),
+    };
+    let segments: Vec<&str> = path.strip_suffix("/.rss")?.split('/').collect();
+
+    match segments[..] {
+        [""] => Some(Topic::Query {
+            release: Release::Any,
+            query: query::decode(q).ok()?,
+        }),
+        ["", "p", id] => Some(Topic::Project(id.to_string())),
+        ["", "p", id, "upgrades"] => Some(Topic::Upgrades(id.to_string())),
+        ["", "authors", author] => Some(Topic::Query {
+            release: Release::Any,
+            query: [(author.to_string(), "*".to_string())]
+                .into_iter()
+                .collect(),
+        }),
+        ["", "packages", author, name] => Some(Topic::Package(format!("{}/{}", author, name))),
+        ["", "stale"] => Some(Topic::Stale),
+        ["", kind] => Some(Topic::Query {
+            release: kind.parse().ok()?,
+            query: query::decode(q).ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Render the feed of a topic, if releases were inserted in it after the
+/// package with id `since`
+fn updated_feed(
+    conn: &SqliteConnection,
+    topic: Topic,
+    since: i32,
+    self_url: &str,
+) -> Option<String> {
+    match topic {
+        Topic::Query { release, query } => {
+            if db::new_packages(conn, query.clone(), &release, since).is_empty() {
+                return None;
+            }
+            Some(rss::all(conn, String::new(), query, &release, self_url))
+        }
+        Topic::Project(id) => {
+            let project = db::projects::get(conn, &id)?;
+            let filter = projects::filter(&projects::packages(conn, &project));
+            let releases = projects::last_releases(conn, filter.clone(), &Release::Any, 42);
+            if !releases.iter().any(|package| package.id > since) {
+                return None;
+            }
+            Some(rss::project(
+                conn,
+                String::new(),
+                &id,
+                filter,
+                &Release::Any,
+                self_url,
+            ))
+        }
+        Topic::Upgrades(id) => {
+            let project = db::projects::get(conn, &id)?;
+            let upgrades = projects::upgrades(conn, &project)?;
+            if !upgrades.iter().any(|upgrade| upgrade.package.id > since) {
+                return None;
+            }
+            Some(rss::upgrades(String::new(), &id, &upgrades, self_url))
+        }
+        Topic::Package(repo) => {
+            let releases = db::package_releases(conn, &repo);
+            if !releases.iter().any(|package| package.id > since) {
+                return None;
+            }
+            Some(rss::history(
+                conn,
+                String::new(),
+                &repo,
+                &releases,
+                self_url,
+            ))
+        }
+        Topic::Stale => {
+            let (latest, _) = rss::stale_releases(conn);
+            if !latest.iter().any(|package| package.id > since) {
+                return None;
+            }
+            Some(rss::stale(conn, String::new(), self_url))
+        }
+    }
+}
+
+/// Start the worker verifying subscription requests
+pub fn verifier(pool: &db::Pool) -> Verifier {
+    let (sender, receiver) = mpsc::sync_channel(MAX_PENDING);
+    let pool = pool.clone();
+    thread::spawn(move || {
+        for request in receiver {
+            verify(&pool, request);
+        }
+    });
+    Verifier(sender)
+}
+
+/// HTTP client for subscribers and webhooks, which must answer quickly and
+/// can't redirect to other hosts.
+pub fn client() -> Client {
+    Client::builder()
+        .timeout(TIMEOUT)
+        .redirect(RedirectPolicy::none())
+        .build()
+        .expect("Can't build HTTP client")
+}
+
+/// Validate a subscription request, then queue the verification of the
+/// intent of the subscriber as required by the WebSub spec.
+pub fn request(verifier: &Verifier, request: Request) -> Result<(), Refusal> {
+    if request.mode != "subscribe" && request.mode != "unsubscribe" {
+        return Err(Refusal::Invalid(format!(
+            "unsupported hub.mode {}",
+            request.mode
+        )));
+    }
+    if callback_url(&request.callback).is_none() {
+        return Err(Refusal::Invalid(format!(
+            "invalid hub.callback {}",
+            request.callback
+        )));
+    }
+    if parse_topic(&request.topic).is_none() {
+        return Err(Refusal::Invalid(format!(
+            "unknown hub.topic {}",
+            request.topic
+        )));
+    }
+    if request.secret.as_ref().map(String::len).unwrap_or(0) > MAX_SECRET_LENGTH {
+        return Err(Refusal::Invalid("hub.secret is too long".to_string()));
+    }
+
+    verifier.0.try_send(request).map_err(|err| match err {
+        TrySendError::Full(_) => Refusal::Busy,
+        TrySendError::Disconnected(_) => panic!("Subscription verifier stopped"),
+    })
+}
+
+/// Parse an HTTP URL of a public host, so that subscribers can't make the
+/// hub send requests to its own network. Host names are checked by
+/// `resolves_publicly` before each request instead, as resolving them can
+/// block and their addresses can change.
+fn callback_url(url: &str) -> Option<Url> {
+    let url = Url::parse(url).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    let host = url.host_str()?;
+    let public = match host.trim_matches(|c| c == '[' || c == ']').parse() {
+        Ok(ip) => public_ip(ip),
+        Err(_) => host != "localhost",
+    };
+    if public {
+        Some(url)
+    } else {
+        None
+    }
+}
+
+/// Whether all addresses of the host of a URL are public
+fn resolves_publicly(url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or_default();
+    (host.trim_matches(|c| c == '[' || c == ']'), port)
+        .to_socket_addrs()
+        .map(|mut addrs| addrs.all(|addr| public_ip(addr.ip())))
+        .unwrap_or(false)
+}
+
+fn public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            // 100.64.0.0/10 is shared by carrier-grade NATs
+            let shared = ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => public_ip(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+fn verify(pool: &db::Pool, request: Request) {
+    let challenge: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    let lease = request
+        .lease_seconds
+        .as_ref()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_LEASE)
+        .clamp(1, MAX_LEASE);
+
+    let mut params = vec![
+        ("hub.mode", request.mode.clone()),
+        ("hub.topic", request.topic.clone()),
+        ("hub.challenge", challenge.clone()),
+    ];
+    if request.mode == "subscribe" {
+        params.push(("hub.lease_seconds", lease.to_string()));
+    }
+
+    let verified = callback_url(&request.callback)
+        .filter(resolves_publicly)
+        .and_then(|url| client().get(url).query(&params).send().ok())
+        .and_then(|mut resp| {
+            let success = resp.status().is_success();
+            resp.text()
+                .ok()
+                .map(|body| success && body.trim() == challenge)
+        })
+        .unwrap_or(false);
+
+    if !verified {
+        log::warn!(
+            callback = request.callback.as_str(), topic = request.topic.as_str();
+            "Can't verify {} of {} to {}", request.mode, request.callback, request.topic
+        );
+        return;
+    }
+
+    let conn = pool.get().expect("Can't get database connection");
+    if request.mode == "subscribe" {
+        db::subscriptions::save(
+            &conn,
+            &NewSubscription {
+                callback: &request.callback,
+                topic: &request.topic,
+                secret: request.secret.as_deref(),
+                expires: Utc::now().timestamp() + lease,
+            },
+        );
+    } else {
+        db::subscriptions::delete(&conn, &request.callback, &request.topic);
+    }
+    log::info!(
+        callback = request.callback.as_str(), topic = request.topic.as_str();
+        "Verified {} of {} to {}", request.mode, request.callback, request.topic
+    );
+}
+
+/// Deliver updated feeds to subscribers of topics matching releases
+/// inserted after the package with id `since`.
+pub fn publish(conn: &SqliteConnection, since: i32) {
+    let client = client();
+
+    for subscription in db::subscriptions::active(conn, Utc::now().timestamp()) {
+        let feed = match parse_topic(&subscription.topic)
+            .and_then(|topic| updated_feed(conn, topic, since, &subscription.topic))
+        {
+            Some(feed) => feed,
+            None => continue,
+        };
+        let url = match callback_url(&subscription.callback).filter(resolves_publicly) {
+            Some(url) => url,
+            None => {
+                log::warn!(
+                    callback = subscription.callback.as_str();
+                    "Can't deliver {} to non public {}", subscription.topic, subscription.callback
+                );
+                continue;
+            }
+        };
+        let mut request = client
+            .post(url)
+            .header(CONTENT_TYPE, "application/rss+xml")
+            .header(
+                LINK,
+                format!(
+                    "<{}>; rel=\"hub\", <{}>; rel=\"self\"",
+                    hub_url(),
+                    subscription.topic
+                ),
+            );
+        if let Some(secret) = &subscription.secret {
+            request = request.header(
+                "X-Hub-Signature",
+                format!("sha256={}", signature(secret, feed.as_bytes())),
+            );
+        }
+
+        let callback = subscription.callback.as_str();
+        match request.body(feed).send() {
+            Ok(resp) if resp.status() == StatusCode::GONE => {
+                log::info!(callback = callback; "Subscriber {} is gone", callback);
+                db::subscriptions::delete(conn, callback, &subscription.topic);
+            }
+            Ok(resp) if resp.status().is_success() => {
+                log::info!(callback = callback; "Delivered {} to {}", subscription.topic, callback)
+            }
+            Ok(resp) => log::warn!(
+                callback = callback;
+                "Can't deliver {} to {}: {}", subscription.topic, callback, resp.status()
+            ),
+            Err(err) => log::warn!(
+                callback = callback;
+                "Can't deliver {} to {}: {}", subscription.topic, callback, err
+            ),
+        }
+    }
+}
+
+/// Hexadecimal HMAC-SHA256 of a body
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_advertised_topics() {
+        let url = |path: &str| format!("{}{}", rss::base_url(), path);
+        assert!(matches!(
+            parse_topic(&url("/stale/.rss")),
+            Some(Topic::Stale)
+        ));
+        assert!(matches!(
+            parse_topic(&url("/packages/elm/core/.rss")),
+            Some(Topic::Package(repo)) if repo == "elm/core"
+        ));
+        assert!(matches!(
+            parse_topic(&url("/last/.rss?elm=core")),
+            Some(Topic::Query {
+                release: Release::Last,
+                ..
+            })
+        ));
+        assert!(parse_topic(&url("/unknown/.rss")).is_none());
+    }
+
+    #[test]
+    fn accepts_public_callbacks() {
+        assert!(callback_url("https://example.com/hook").is_some());
+        assert!(callback_url("http://93.184.216.34:8080/hook").is_some());
+        assert!(callback_url("http://[2606:2800:220:1::1]/").is_some());
+    }
+
+    #[test]
+    fn refuses_local_callbacks() {
+        for url in &[
+            "ftp://example.com/hook",
+            "http://localhost:4242/",
+            "http://127.0.0.1/",
+            "http://10.0.0.1/",
+            "http://192.168.1.1/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "not a url",
+        ] {
+            assert!(callback_url(url).is_none(), "{}", url);
+        }
+    }
+}
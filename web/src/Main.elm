@@ -121,19 +121,40 @@ paths =
         , "/major"
         , "/minor"
         , "/patch"
+        , "/stale"
         , "/help"
         ]
 
 
 canonicalizeUrl : Url -> Url
 canonicalizeUrl url =
-    if Set.member url.path paths then
+    if Set.member url.path paths || isPackagePath url.path || isAuthorPath url.path then
         { url | path = url.path }
 
     else
         { url | path = "/" }
 
 
+isPackagePath : String -> Bool
+isPackagePath path =
+    case String.split "/" path of
+        [ "", "packages", author, name ] ->
+            author /= "" && name /= ""
+
+        _ ->
+            False
+
+
+isAuthorPath : String -> Bool
+isAuthorPath path =
+    case String.split "/" path of
+        [ "", "authors", author ] ->
+            author /= ""
+
+        _ ->
+            False
+
+
 getPage : Url -> Page -> Page
 getPage url page =
     case url.path of